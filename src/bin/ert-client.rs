@@ -0,0 +1,296 @@
+//! Sends echo and add requests to a running server.
+//!
+//! Requests come from the command line, an interactive prompt or a script
+//! with one request per line:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! echo hello world
+//! add 2 40
+//! ```
+
+use clap::{Parser, Subcommand, ValueEnum};
+use embedded_recruitment_task::{
+    client::Client,
+    message::{client_message, server_message, AddRequest, EchoMessage, ServerMessage},
+    Error,
+};
+use serde_json::json;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+/// A script line could not be parsed (sysexits `EX_DATAERR`)
+const EXIT_DATA: u8 = 65;
+/// The server could not be reached (sysexits `EX_UNAVAILABLE`)
+const EXIT_UNAVAILABLE: u8 = 69;
+/// The server answered at least one request with an error
+const EXIT_ERROR_RESPONSE: u8 = 1;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ert-client",
+    version,
+    about = "Client for the echo and add server"
+)]
+struct Args {
+    /// Server address
+    #[arg(short, long, default_value = "127.0.0.1:8080", value_parser = split_addr)]
+    addr: (String, u16),
+
+    /// How responses are printed
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Milliseconds to wait for each response
+    #[arg(short, long, default_value_t = 5000)]
+    timeout: u64,
+
+    /// Authenticates with this token right after connecting
+    #[arg(long)]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    /// One JSON object per response
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Sends an echo request
+    Echo {
+        /// Words to echo, joined with spaces
+        #[arg(required = true)]
+        words: Vec<String>,
+    },
+    /// Sends an add request
+    Add {
+        #[arg(allow_negative_numbers = true)]
+        a: i32,
+        #[arg(allow_negative_numbers = true)]
+        b: i32,
+    },
+    /// Reads requests interactively until EOF or `quit`
+    Repl,
+    /// Runs the requests in a script file, or stdin for `-`
+    Script { file: PathBuf },
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let (host, port) = args.addr;
+    let mut connection = Client::new(&host, port.into(), args.timeout);
+    if let Some(token) = args.token {
+        connection = connection.with_auth_token(token);
+    }
+    if let Err(e) = connection.connect() {
+        eprintln!("Failed to connect to {}:{}: {}", host, port, e);
+        return ExitCode::from(EXIT_UNAVAILABLE);
+    }
+
+    let result = match args.command {
+        Command::Echo { words } => run_one(&mut connection, echo(words.join(" ")), args.format),
+        Command::Add { a, b } => run_one(&mut connection, add(a, b), args.format),
+        Command::Repl => {
+            let stdin = io::stdin();
+            let prompt = stdin.is_terminal();
+            run_lines(&mut connection, stdin.lock(), args.format, prompt)
+        }
+        Command::Script { file } if file.as_os_str() == "-" => {
+            run_lines(&mut connection, io::stdin().lock(), args.format, false)
+        }
+        Command::Script { file } => match File::open(&file) {
+            Ok(script) => run_lines(&mut connection, BufReader::new(script), args.format, false),
+            Err(e) => {
+                eprintln!("Failed to open {}: {}", file.display(), e);
+                return ExitCode::from(EXIT_DATA);
+            }
+        },
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_ERROR_RESPONSE),
+        Err(Failure::Script(e)) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_DATA)
+        }
+        Err(Failure::Client(e)) => {
+            eprintln!("Request failed: {}", e);
+            ExitCode::from(EXIT_UNAVAILABLE)
+        }
+    }
+}
+
+/// Splits `host:port` for [`Client::new`]; IPv6 hosts keep their brackets
+/// so the client can join them again
+fn split_addr(addr: &str) -> Result<(String, u16), String> {
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| "expected host:port".to_string())?;
+    let port = port.parse().map_err(|e| format!("invalid port: {}", e))?;
+    Ok((host.to_string(), port))
+}
+
+enum Failure {
+    Script(String),
+    Client(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Client(e)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Client(e.into())
+    }
+}
+
+/// Sends `message` and returns the raw response, errors included
+fn request(
+    connection: &mut Client,
+    message: client_message::Message,
+) -> Result<ServerMessage, Error> {
+    let request_id = connection.send_request(message)?;
+    connection.receive_response(request_id)
+}
+
+/// Sends a single request; `Ok(false)` if the server answered with an error
+fn run_one(
+    connection: &mut Client,
+    message: client_message::Message,
+    format: Format,
+) -> Result<bool, Failure> {
+    let response = request(connection, message)?;
+    Ok(print_response(&response, format))
+}
+
+/// Runs one request per line. The prompt is only shown on a terminal, where
+/// bad lines are reported instead of ending the session.
+fn run_lines(
+    connection: &mut Client,
+    input: impl BufRead,
+    format: Format,
+    interactive: bool,
+) -> Result<bool, Failure> {
+    let mut all_ok = true;
+    if interactive {
+        show_prompt();
+    }
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        if !line.is_empty() && !line.starts_with('#') {
+            match parse_line(line) {
+                Ok(message) => all_ok &= print_response(&request(connection, message)?, format),
+                Err(e) if interactive => eprintln!("{}", e),
+                Err(e) => return Err(Failure::Script(format!("line {}: {}", number + 1, e))),
+            }
+        }
+        if interactive {
+            show_prompt();
+        }
+    }
+    Ok(all_ok)
+}
+
+fn show_prompt() {
+    print!("> ");
+    let _ = io::stdout().flush();
+}
+
+fn parse_line(line: &str) -> Result<client_message::Message, String> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    match command {
+        "echo" => Ok(echo(rest.trim().to_string())),
+        "add" => {
+            let operands: Vec<&str> = rest.split_whitespace().collect();
+            let [a, b] = operands[..] else {
+                return Err("usage: add <a> <b>".to_string());
+            };
+            let parse = |operand: &str| {
+                operand
+                    .parse::<i32>()
+                    .map_err(|e| format!("invalid operand {:?}: {}", operand, e))
+            };
+            Ok(add(parse(a)?, parse(b)?))
+        }
+        _ => Err(format!(
+            "unknown command {:?}; expected echo or add",
+            command
+        )),
+    }
+}
+
+fn echo(content: String) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage { content })
+}
+
+fn add(a: i32, b: i32) -> client_message::Message {
+    client_message::Message::AddRequest(AddRequest { a, b })
+}
+
+/// Prints `response` and returns whether it was a success
+fn print_response(response: &ServerMessage, format: Format) -> bool {
+    let id = response.request_id;
+    let (text, value, ok) = match response.message {
+        Some(server_message::Message::EchoMessage(ref echo)) => (
+            echo.content.clone(),
+            json!({ "request_id": id, "echo": echo.content }),
+            true,
+        ),
+        Some(server_message::Message::AddResponse(ref add)) => {
+            // The widened sum is only filled in under the widen policy
+            let text = if add.wide_result != 0 && add.wide_result != i64::from(add.result) {
+                add.wide_result.to_string()
+            } else {
+                add.result.to_string()
+            };
+            let value = json!({
+                "request_id": id,
+                "add": { "result": add.result, "wide_result": add.wide_result },
+            });
+            (text, value, true)
+        }
+        Some(server_message::Message::AuthResponse(ref auth)) => (
+            format!("authenticated as {}", auth.principal),
+            json!({ "request_id": id, "auth": { "principal": auth.principal } }),
+            true,
+        ),
+        Some(server_message::Message::ErrorResponse(ref error)) => (
+            format!("error {}: {}", error.code().as_str_name(), error.detail),
+            json!({
+                "request_id": id,
+                "error": { "code": error.code().as_str_name(), "detail": error.detail },
+            }),
+            false,
+        ),
+        None => (
+            "error: empty response".to_string(),
+            json!({ "request_id": id, "error": null }),
+            false,
+        ),
+    };
+
+    match format {
+        Format::Text => println!("{}", text),
+        Format::Json => println!("{}", value),
+    }
+    ok
+}
@@ -0,0 +1,121 @@
+//! Runs the server until SIGINT or SIGTERM, then drains it gracefully.
+//!
+//! Settings are taken from the defaults, then the config file, then
+//! `ERT_SERVER_*` environment variables and finally the command line.
+
+use clap::Parser;
+use embedded_recruitment_task::server::{ConfigError, ServerBuilder, ServerConfig};
+use log::{error, info, LevelFilter};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{path::PathBuf, process::ExitCode, sync::Arc, thread};
+
+/// The configuration is invalid (sysexits `EX_CONFIG`)
+const EXIT_CONFIG: u8 = 78;
+/// The listener could not be bound (sysexits `EX_UNAVAILABLE`)
+const EXIT_BIND: u8 = 69;
+/// The server failed while running
+const EXIT_FAILURE: u8 = 1;
+
+#[derive(Debug, Parser)]
+#[command(name = "ert-server", version, about = "Echo and add protobuf server")]
+struct Args {
+    /// Address to listen on, e.g. 0.0.0.0:8080
+    #[arg(short, long)]
+    addr: Option<String>,
+
+    /// TOML configuration file
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Number of worker threads running request handlers
+    #[arg(short, long)]
+    workers: Option<usize>,
+
+    /// Log level; defaults to RUST_LOG, or info if that is unset
+    #[arg(short, long, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+
+    /// Print the effective configuration and exit
+    #[arg(long)]
+    print_config: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if let Some(level) = args.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+    if args.print_config {
+        print!("{}", config);
+        return ExitCode::SUCCESS;
+    }
+    info!("Effective configuration:\n{}", config);
+
+    let server = match ServerBuilder::from_config(config).build() {
+        Ok(server) => Arc::new(server),
+        Err(ConfigError::Io(e)) => {
+            error!("Failed to bind: {}", e);
+            return ExitCode::from(EXIT_BIND);
+        }
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            error!("Failed to install signal handlers: {}", e);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+    {
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                info!("Received signal {}, shutting down", signal);
+                server.stop();
+            }
+        });
+    }
+
+    match server.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("Server failed: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+/// Layers the config file, the environment and the flags over the defaults
+fn load_config(args: &Args) -> Result<ServerConfig, ConfigError> {
+    let mut config = match args.config {
+        Some(ref path) => ServerConfig::from_file(path)?,
+        None => ServerConfig::default(),
+    };
+    config.apply_env()?;
+    if let Some(ref addr) = args.addr {
+        config.addr = addr.clone();
+    }
+    if let Some(workers) = args.workers {
+        config.workers = workers;
+    }
+    Ok(config)
+}
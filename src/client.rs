@@ -0,0 +1,513 @@
+//! Blocking client for the framed protocol spoken by [`Server`](crate::server::Server).
+//!
+//! ```no_run
+//! use embedded_recruitment_task::client::Client;
+//!
+//! let mut client = Client::new("localhost", 8080, 1000);
+//! client.connect()?;
+//! assert_eq!(client.echo("hello")?, "hello");
+//! assert_eq!(client.add(2, 40)?, 42);
+//! # Ok::<(), embedded_recruitment_task::Error>(())
+//! ```
+
+use crate::{
+    error::{Error, Result},
+    framing::{write_frame, FrameDecoder, DEFAULT_MAX_FRAME_SIZE},
+    message::{
+        client_message, server_message, AddRequest, AuthRequest, ClientMessage, EchoMessage,
+        ServerMessage,
+    },
+};
+use log::{debug, info, warn};
+use prost::Message;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+use self::stream::Stream;
+pub use self::{
+    pool::{ClientPool, PoolConfig, PoolStatus, PooledClient},
+    reconnect::{ConnectionState, ReconnectPolicy, Replay},
+};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+mod pool;
+mod reconnect;
+mod stream;
+
+type StateListener = Box<dyn FnMut(ConnectionState) + Send>;
+
+/// Connection to a server that can have several requests in flight.
+///
+/// Every request is tagged with a fresh ID. Responses can be collected in
+/// any order with [`Client::receive_response`]; the typed helpers such as
+/// [`Client::echo`] send one request and wait for its answer.
+///
+/// By default a lost connection is reported as an error. With
+/// [`Client::with_reconnect`] the client reconnects on its own and replays
+/// the unanswered requests the [`ReconnectPolicy`] allows.
+///
+/// Servers that require authentication are sent the token given to
+/// [`Client::with_auth_token`] or [`Client::authenticate`] on every new
+/// connection, before any other request.
+pub struct Client {
+    ip: String,
+    port: u32,
+    timeout: Duration,
+    max_frame_size: usize,
+    stream: Option<Stream>,
+    frames: FrameDecoder,
+    next_request_id: u64,
+    pending: HashMap<u64, ServerMessage>, // Responses read while waiting for another ID
+    reconnect: Option<ReconnectPolicy>,
+    listener: Option<StateListener>,
+    wants_connection: bool, // Connected by the caller and not disconnected since
+    unanswered: BTreeMap<u64, client_message::Message>, // In flight; kept for replay
+    lost: BTreeSet<u64>,    // Requests dropped by a reconnect, not yet reported
+    auth_token: Option<String>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Client {
+    /// Creates a disconnected client. `timeout_ms` bounds both connecting
+    /// and waiting for each response.
+    pub fn new(ip: &str, port: u32, timeout_ms: u64) -> Self {
+        Client {
+            ip: ip.to_string(),
+            port,
+            timeout: Duration::from_millis(timeout_ms),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            stream: None,
+            frames: FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE),
+            next_request_id: 1,
+            pending: HashMap::new(),
+            reconnect: None,
+            listener: None,
+            wants_connection: false,
+            unanswered: BTreeMap::new(),
+            lost: BTreeSet::new(),
+            auth_token: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Reconnects automatically when the connection is lost
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Authenticates with `token` each time the client connects
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Calls `listener` whenever the connection state changes
+    pub fn with_state_listener<F>(mut self, listener: F) -> Self
+    where
+        F: FnMut(ConnectionState) + Send + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Connects over TLS, trusting the server certificates that chain up to
+    /// `roots`. The certificate must be valid for the host name or IP
+    /// address the client was created with.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(self, roots: rustls::RootCertStore) -> Self {
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        self.with_tls_config(Arc::new(config))
+    }
+
+    /// Connects over TLS with a complete rustls configuration
+    #[cfg(feature = "tls")]
+    pub fn with_tls_config(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Sets the largest frame payload sent to or accepted from the server
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self.frames = FrameDecoder::new(max_frame_size);
+        self
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Checks without blocking that the server has not closed the connection
+    /// and that no response is left unread
+    pub(crate) fn is_idle(&mut self) -> bool {
+        if !self.pending.is_empty() || !self.unanswered.is_empty() {
+            return false;
+        }
+        let Some(ref mut stream) = self.stream else {
+            return false;
+        };
+        // Anything readable is either EOF or a response nobody waited for
+        let mut byte = [0u8; 1];
+        matches!(stream.read_available(&mut byte), Ok(None))
+    }
+
+    /// Connects to the server, replacing any previous connection
+    pub fn connect(&mut self) -> Result<()> {
+        self.pending.clear();
+        self.unanswered.clear();
+        self.lost.clear();
+        self.open()?;
+        self.wants_connection = true;
+        self.notify(ConnectionState::Connected);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let address = format!("{}:{}", self.ip, self.port);
+        info!("Connecting to {}", address);
+
+        let socket_addrs: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+        let mut last_error = None;
+        for addr in socket_addrs {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    // Fail a receive instead of hanging when the server never answers
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    self.stream = Some(self.wrap(stream)?);
+                    self.frames = FrameDecoder::new(self.max_frame_size);
+                    info!("Connected to {}", addr);
+                    if let Err(e) = self.send_auth_token() {
+                        self.stream = None;
+                        return Err(e);
+                    }
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid IP or port"))
+            .into())
+    }
+
+    /// Authenticates a fresh connection, before anything else is sent on it
+    fn send_auth_token(&mut self) -> Result<()> {
+        let Some(token) = self.auth_token.clone() else {
+            return Ok(());
+        };
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let request = client_message::Message::AuthRequest(AuthRequest { token });
+        self.write(request_id, request)?;
+        let principal = auth_principal(self.read_message()?)?;
+        info!("Authenticated as {}", principal);
+        Ok(())
+    }
+
+    #[cfg(feature = "tls")]
+    fn wrap(&self, mut socket: TcpStream) -> Result<Stream> {
+        let Some(ref config) = self.tls else {
+            return Ok(Stream::Plain(socket));
+        };
+        let name = rustls::pki_types::ServerName::try_from(self.ip.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut session = rustls::ClientConnection::new(Arc::clone(config), name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // Handshake right away so certificate problems surface on connect
+        while session.is_handshaking() {
+            session.complete_io(&mut socket)?;
+        }
+        Ok(Stream::Tls(Box::new(rustls::StreamOwned::new(
+            session, socket,
+        ))))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn wrap(&self, socket: TcpStream) -> Result<Stream> {
+        Ok(Stream::Plain(socket))
+    }
+
+    /// Closes the connection; does nothing if there is none
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.wants_connection = false;
+        self.unanswered.clear();
+        if let Some(mut stream) = self.stream.take() {
+            stream.shutdown()?;
+            info!("Disconnected from {}:{}", self.ip, self.port);
+        }
+        Ok(())
+    }
+
+    /// Sends `message` without waiting for the response
+    pub fn send(&mut self, message: client_message::Message) -> Result<()> {
+        self.send_request(message).map(|_| ())
+    }
+
+    /// Sends `message` tagged with a fresh request ID and returns that ID
+    pub fn send_request(&mut self, message: client_message::Message) -> Result<u64> {
+        if self.stream.is_none() && self.can_recover() {
+            // An earlier reconnect gave up; try again for this request
+            self.recover()?;
+        }
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+
+        self.unanswered.insert(request_id, message.clone());
+        match self.write(request_id, message) {
+            Ok(()) => Ok(request_id),
+            Err(e) if self.can_recover() && e.is_connection_lost() => {
+                // Replaying covers this request too, if the policy allows it
+                self.lose_connection();
+                let recovered = self.recover();
+                // The caller learns this ID only on success, so a failed
+                // request must not be reported again by `receive`
+                let lost = self.lost.remove(&request_id);
+                match recovered {
+                    Ok(()) if !lost => Ok(request_id),
+                    Ok(()) => Err(e),
+                    Err(e) => {
+                        self.unanswered.remove(&request_id);
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => {
+                self.unanswered.remove(&request_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn write(&mut self, request_id: u64, message: client_message::Message) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        let envelope = ClientMessage {
+            message: Some(message),
+            request_id,
+        };
+        write_frame(stream, &envelope, self.max_frame_size)?;
+        debug!("Sent request #{}", request_id);
+        Ok(())
+    }
+
+    /// Receives the response to `request_id`, keeping any others for later.
+    ///
+    /// A connection-level error (request ID 0) is returned as well, since the
+    /// server sends it instead of the response that was asked for.
+    pub fn receive_response(&mut self, request_id: u64) -> Result<ServerMessage> {
+        if let Some(response) = self.pending.remove(&request_id) {
+            return Ok(response);
+        }
+
+        loop {
+            if self.lost.remove(&request_id) {
+                return Err(Error::Disconnected);
+            }
+            let response = self.read_response()?;
+            if response.request_id == request_id || response.request_id == 0 {
+                return Ok(response);
+            }
+            self.pending.insert(response.request_id, response);
+        }
+    }
+
+    /// Receives the next response, whatever request it answers
+    pub fn receive(&mut self) -> Result<ServerMessage> {
+        // Hand out responses buffered by `receive_response` first
+        if let Some(&request_id) = self.pending.keys().min() {
+            return Ok(self.pending.remove(&request_id).unwrap());
+        }
+        // Requests dropped by a reconnect count as answered with an error
+        if self.lost.pop_first().is_some() {
+            return Err(Error::Disconnected);
+        }
+        self.read_response()
+    }
+
+    /// Sends `message` and waits for its response, turning an
+    /// `ErrorResponse` into [`Error::Server`]
+    pub fn request(&mut self, message: client_message::Message) -> Result<server_message::Message> {
+        let request_id = self.send_request(message)?;
+        match self.receive_response(request_id)?.message {
+            Some(server_message::Message::ErrorResponse(error)) => Err(Error::Server {
+                code: error.code(),
+                detail: error.detail,
+            }),
+            Some(response) => Ok(response),
+            None => Err(Error::UnexpectedResponse { expected: "any" }),
+        }
+    }
+
+    /// Asks the server to echo `content` back
+    pub fn echo(&mut self, content: &str) -> Result<String> {
+        let request = client_message::Message::EchoMessage(EchoMessage {
+            content: content.to_string(),
+        });
+        match self.request(request)? {
+            server_message::Message::EchoMessage(echo) => Ok(echo.content),
+            _ => Err(Error::UnexpectedResponse {
+                expected: "EchoMessage",
+            }),
+        }
+    }
+
+    /// Authenticates the connection with `token` and returns who the server
+    /// knows it as. The token is kept and sent again after reconnecting.
+    pub fn authenticate(&mut self, token: &str) -> Result<String> {
+        let request = client_message::Message::AuthRequest(AuthRequest {
+            token: token.to_string(),
+        });
+        let request_id = self.send_request(request)?;
+        let principal = auth_principal(self.receive_response(request_id)?)?;
+        self.auth_token = Some(token.to_string());
+        Ok(principal)
+    }
+
+    /// Asks the server for `a + b`.
+    ///
+    /// The sum is widened to `i64` so that a server configured with
+    /// [`OverflowPolicy::Widen`](crate::handler::OverflowPolicy::Widen)
+    /// can report sums beyond `i32`.
+    pub fn add(&mut self, a: i32, b: i32) -> Result<i64> {
+        let request = client_message::Message::AddRequest(AddRequest { a, b });
+        match self.request(request)? {
+            // `wide_result` is only filled in under the widen policy, where
+            // it is zero exactly when the sum is
+            server_message::Message::AddResponse(add) if add.wide_result != 0 => {
+                Ok(add.wide_result)
+            }
+            server_message::Message::AddResponse(add) => Ok(i64::from(add.result)),
+            _ => Err(Error::UnexpectedResponse {
+                expected: "AddResponse",
+            }),
+        }
+    }
+
+    /// Reads the next response, reconnecting first if the connection drops
+    fn read_response(&mut self) -> Result<ServerMessage> {
+        loop {
+            match self.read_message() {
+                Ok(response) => {
+                    self.unanswered.remove(&response.request_id);
+                    return Ok(response);
+                }
+                Err(e) if self.can_recover() && e.is_connection_lost() => {
+                    self.lose_connection();
+                    self.recover()?;
+                    if self.unanswered.is_empty() {
+                        // Nothing was replayed, so no response is coming
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn can_recover(&self) -> bool {
+        self.reconnect.is_some() && self.wants_connection
+    }
+
+    fn lose_connection(&mut self) {
+        self.stream = None;
+        self.notify(ConnectionState::Disconnected);
+    }
+
+    /// Reconnects with backoff and replays the unanswered requests
+    fn recover(&mut self) -> Result<()> {
+        let Some(policy) = self.reconnect.clone() else {
+            return Err(Error::NotConnected);
+        };
+
+        let mut last_error = Error::NotConnected;
+        for attempt in 1..=policy.max_attempts {
+            let delay = policy.backoff(attempt);
+            self.notify(ConnectionState::Reconnecting { attempt, delay });
+            thread::sleep(delay);
+            match self.open() {
+                Ok(()) => {
+                    self.notify(ConnectionState::Connected);
+                    return self.replay(policy.replay);
+                }
+                Err(e) => {
+                    warn!("Reconnect attempt {} failed: {}", attempt, e);
+                    last_error = e;
+                }
+            }
+        }
+
+        self.notify(ConnectionState::GaveUp);
+        let unanswered = std::mem::take(&mut self.unanswered);
+        self.lost.extend(unanswered.into_keys());
+        Err(last_error)
+    }
+
+    /// Sends the unanswered requests again on a fresh connection
+    fn replay(&mut self, replay: Replay) -> Result<()> {
+        let mut unanswered = std::mem::take(&mut self.unanswered).into_iter();
+        while let Some((request_id, message)) = unanswered.next() {
+            if !replay.allows(&message) {
+                self.lost.insert(request_id);
+                continue;
+            }
+            debug!("Replaying request #{}", request_id);
+            self.unanswered.insert(request_id, message.clone());
+            if let Err(e) = self.write(request_id, message) {
+                // Keep the rest for the next attempt
+                self.unanswered.extend(unanswered);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn notify(&mut self, state: ConnectionState) {
+        info!("Connection to {}:{} {}", self.ip, self.port, state);
+        if let Some(ref mut listener) = self.listener {
+            listener(state);
+        }
+    }
+
+    fn read_message(&mut self) -> Result<ServerMessage> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        let frame = match self.frames.read_frame(stream) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                info!("Server disconnected.");
+                self.stream = None;
+                return Err(Error::Disconnected);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        debug!("Received {} bytes from the server", frame.len());
+        Ok(ServerMessage::decode(frame.as_slice())?)
+    }
+}
+
+/// The principal in the answer to an `AuthRequest`
+fn auth_principal(response: ServerMessage) -> Result<String> {
+    match response.message {
+        Some(server_message::Message::AuthResponse(auth)) => Ok(auth.principal),
+        Some(server_message::Message::ErrorResponse(error)) => Err(Error::Server {
+            code: error.code(),
+            detail: error.detail,
+        }),
+        _ => Err(Error::UnexpectedResponse {
+            expected: "AuthResponse",
+        }),
+    }
+}
@@ -0,0 +1,407 @@
+use super::{Client, ReconnectPolicy};
+use crate::error::{Error, Result};
+use log::{debug, warn};
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shortest pause between two passes of the idle reaper
+const MIN_REAP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Sizing and upkeep of a [`ClientPool`].
+#[derive(Clone, PartialEq)]
+pub struct PoolConfig {
+    /// Connections opened up front and kept through idle eviction
+    pub min_connections: usize,
+    /// Connections open at once, checked out or idle
+    pub max_connections: usize,
+    /// How long `checkout` waits for a connection when all are in use
+    pub checkout_timeout: Duration,
+    /// Idle connections beyond `min_connections` are closed after this long,
+    /// by a background thread that checks every half timeout
+    pub idle_timeout: Option<Duration>,
+    /// Connections idle this long answer an empty echo before checkout
+    pub health_check_after: Option<Duration>,
+    /// Passed on to every pooled client
+    pub reconnect: Option<ReconnectPolicy>,
+    /// Token every pooled client authenticates with
+    pub auth_token: Option<String>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            min_connections: 0,
+            max_connections: 8,
+            checkout_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(60)),
+            health_check_after: Some(Duration::from_secs(10)),
+            reconnect: None,
+            auth_token: None,
+        }
+    }
+}
+
+impl fmt::Debug for PoolConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolConfig")
+            .field("min_connections", &self.min_connections)
+            .field("max_connections", &self.max_connections)
+            .field("checkout_timeout", &self.checkout_timeout)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("health_check_after", &self.health_check_after)
+            .field("reconnect", &self.reconnect)
+            // Never print the token itself
+            .field("auth_token", &self.auth_token.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
+
+/// Connection counts at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStatus {
+    /// Connections checked out plus idle ones
+    pub open: usize,
+    pub idle: usize,
+}
+
+/// Thread-safe set of [`Client`] connections to one server.
+///
+/// Clones share the same connections, so a pool can be handed to as many
+/// threads as needed. [`ClientPool::checkout`] lends out a connection until
+/// the returned [`PooledClient`] is dropped.
+///
+/// ```no_run
+/// use embedded_recruitment_task::client::{ClientPool, PoolConfig};
+/// use std::thread;
+///
+/// let pool = ClientPool::new("localhost", 8080, 1000, PoolConfig::default())?;
+/// let workers: Vec<_> = (0..4)
+///     .map(|i| {
+///         let pool = pool.clone();
+///         thread::spawn(move || pool.checkout()?.add(i, i))
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap()?;
+/// }
+/// # Ok::<(), embedded_recruitment_task::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct ClientPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    ip: String,
+    port: u32,
+    timeout_ms: u64,
+    config: PoolConfig,
+    state: Mutex<State>,
+    returned: Condvar,
+    reaper: Arc<Reaper>,
+}
+
+/// Stop signal of the thread evicting idle connections, which only holds
+/// the pool weakly
+#[derive(Default)]
+struct Reaper {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    idle: VecDeque<Idle>, // Most recently returned at the back
+    open: usize,
+}
+
+struct Idle {
+    client: Client,
+    since: Instant,
+}
+
+impl ClientPool {
+    /// Creates a pool and opens `config.min_connections` right away
+    pub fn new(ip: &str, port: u32, timeout_ms: u64, config: PoolConfig) -> Result<Self> {
+        let config = PoolConfig {
+            max_connections: config.max_connections.max(config.min_connections).max(1),
+            ..config
+        };
+        let pool = ClientPool {
+            shared: Arc::new(Shared {
+                ip: ip.to_string(),
+                port,
+                timeout_ms,
+                config,
+                state: Mutex::new(State::default()),
+                returned: Condvar::new(),
+                reaper: Arc::default(),
+            }),
+        };
+        if let Some(timeout) = pool.shared.config.idle_timeout {
+            pool.spawn_reaper(timeout)?;
+        }
+
+        for _ in 0..pool.shared.config.min_connections {
+            let client = pool.shared.open()?;
+            let mut state = pool.shared.lock();
+            state.open += 1;
+            state.idle.push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+        Ok(pool)
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.shared.config
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        let state = self.shared.lock();
+        PoolStatus {
+            open: state.open,
+            idle: state.idle.len(),
+        }
+    }
+
+    /// Lends out an idle connection, opening a new one if the pool is below
+    /// `max_connections`, or waiting up to `checkout_timeout` for one to be
+    /// returned
+    pub fn checkout(&self) -> Result<PooledClient> {
+        let shared = &self.shared;
+        let deadline = Instant::now() + shared.config.checkout_timeout;
+        let mut state = shared.lock();
+        loop {
+            shared.evict(&mut state);
+
+            if let Some(idle) = state.idle.pop_back() {
+                drop(state);
+                match shared.check(idle) {
+                    Some(client) => return Ok(self.lend(client)),
+                    None => {
+                        state = shared.lock();
+                        state.open -= 1;
+                        continue;
+                    }
+                }
+            }
+
+            if state.open < shared.config.max_connections {
+                // Reserve the slot, then connect without holding the lock
+                state.open += 1;
+                drop(state);
+                return match shared.open() {
+                    Ok(client) => Ok(self.lend(client)),
+                    Err(e) => {
+                        shared.release();
+                        Err(e)
+                    }
+                };
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                warn!(
+                    "All {} pooled connections are in use",
+                    shared.config.max_connections
+                );
+                return Err(Error::PoolTimeout {
+                    waited: shared.config.checkout_timeout,
+                });
+            }
+            state = shared
+                .returned
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Closes idle connections past `idle_timeout`, keeping
+    /// `min_connections` open, and returns how many were closed
+    pub fn evict_idle(&self) -> usize {
+        self.shared.evict(&mut self.shared.lock())
+    }
+
+    /// Evicts idle connections even while nobody uses the pool, until the
+    /// last clone of it is dropped
+    fn spawn_reaper(&self, timeout: Duration) -> Result<()> {
+        let interval = (timeout / 2).max(MIN_REAP_INTERVAL);
+        let shared = Arc::downgrade(&self.shared);
+        let reaper = Arc::clone(&self.shared.reaper);
+        thread::Builder::new()
+            .name("pool-reaper".to_string())
+            .spawn(move || reap(&shared, &reaper, interval))?;
+        Ok(())
+    }
+
+    fn lend(&self, client: Client) -> PooledClient {
+        PooledClient {
+            client: Some(client),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+fn reap(shared: &Weak<Shared>, reaper: &Reaper, interval: Duration) {
+    let mut stopped = reaper.stopped.lock().unwrap();
+    loop {
+        stopped = reaper
+            .wake
+            .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+            .unwrap()
+            .0;
+        if *stopped {
+            return;
+        }
+        drop(stopped);
+        // Gone once the last clone of the pool was dropped
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        shared.evict(&mut shared.lock());
+        drop(shared);
+        stopped = reaper.stopped.lock().unwrap();
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        *self.reaper.stopped.lock().unwrap() = true;
+        self.reaper.wake.notify_all();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn open(&self) -> Result<Client> {
+        let mut client = Client::new(&self.ip, self.port, self.timeout_ms);
+        if let Some(ref policy) = self.config.reconnect {
+            client = client.with_reconnect(policy.clone());
+        }
+        if let Some(ref token) = self.config.auth_token {
+            client = client.with_auth_token(token.clone());
+        }
+        client.connect()?;
+        debug!("Opened pooled connection to {}:{}", self.ip, self.port);
+        Ok(client)
+    }
+
+    /// Returns the client if it is still usable
+    fn check(&self, idle: Idle) -> Option<Client> {
+        let mut client = idle.client;
+        if !client.is_idle() {
+            debug!("Dropping pooled connection closed by the server");
+            return None;
+        }
+        let stale = self
+            .config
+            .health_check_after
+            .is_some_and(|after| idle.since.elapsed() >= after);
+        if stale {
+            if let Err(e) = client.echo("") {
+                debug!(
+                    "Dropping pooled connection that failed its health check: {}",
+                    e
+                );
+                return None;
+            }
+        }
+        Some(client)
+    }
+
+    fn evict(&self, state: &mut State) -> usize {
+        let Some(timeout) = self.config.idle_timeout else {
+            return 0;
+        };
+        let mut evicted = 0;
+        // The front has been idle longest
+        while state.open > self.config.min_connections
+            && state
+                .idle
+                .front()
+                .is_some_and(|idle| idle.since.elapsed() >= timeout)
+        {
+            state.idle.pop_front();
+            state.open -= 1;
+            evicted += 1;
+        }
+        if evicted > 0 {
+            debug!("Evicted {} idle pooled connections", evicted);
+        }
+        evicted
+    }
+
+    /// Frees the slot of a connection that was closed instead of returned
+    fn release(&self) {
+        self.lock().open -= 1;
+        self.returned.notify_one();
+    }
+
+    fn give_back(&self, mut client: Client) {
+        if !client.is_idle() {
+            self.release();
+            return;
+        }
+        let mut state = self.lock();
+        state.idle.push_back(Idle {
+            client,
+            since: Instant::now(),
+        });
+        self.evict(&mut state);
+        drop(state);
+        self.returned.notify_one();
+    }
+}
+
+/// Connection checked out of a [`ClientPool`], returned to it on drop.
+///
+/// A connection that was closed, or that still has responses in flight, is
+/// discarded rather than handed to the next caller.
+pub struct PooledClient {
+    client: Option<Client>,
+    shared: Arc<Shared>,
+}
+
+impl PooledClient {
+    /// Closes the connection instead of returning it to the pool
+    pub fn discard(mut self) {
+        if let Some(mut client) = self.client.take() {
+            let _ = client.disconnect();
+        }
+        self.shared.release();
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.shared.give_back(client);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{handler::MessageKind, message::client_message};
+use std::{fmt, time::Duration};
+
+/// How a [`Client`](super::Client) recovers from a lost connection.
+///
+/// Reconnect attempts are spaced with exponential backoff: the delay starts
+/// at `initial_backoff`, doubles with every attempt up to `max_backoff`, and
+/// each delay is shortened by a random fraction of up to `jitter` so that
+/// many clients do not hammer a restarting server in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Between 0.0 (fixed delays) and 1.0 (anywhere from zero to the delay)
+    pub jitter: f64,
+    /// Attempts before giving up and returning the error to the caller
+    pub max_attempts: u32,
+    /// Which requests that were still unanswered are sent again
+    pub replay: Replay,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.5,
+            max_attempts: 5,
+            replay: Replay::default(),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before the given reconnect attempt, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        let jitter = self.jitter.clamp(0.0, 1.0) * fastrand::f64();
+        delay.mul_f64(1.0 - jitter)
+    }
+}
+
+/// Requests sent again after reconnecting, in their original order.
+///
+/// Requests that are not replayed fail with
+/// [`Error::Disconnected`](crate::Error::Disconnected), since
+/// there is no telling whether the server processed them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Replay {
+    /// Never resend; every unanswered request fails
+    Never,
+    /// Resend requests whose kind is idempotent
+    #[default]
+    Idempotent,
+    /// Resend everything, accepting that a request may be processed twice.
+    /// `AuthRequest`s are still never resent, since reconnecting already
+    /// authenticates the new connection
+    Always,
+}
+
+impl Replay {
+    pub fn allows(self, request: &client_message::Message) -> bool {
+        let kind = MessageKind::of(request);
+        match self {
+            Replay::Never => false,
+            _ if kind == MessageKind::Auth => false,
+            Replay::Idempotent => kind.is_idempotent(),
+            Replay::Always => true,
+        }
+    }
+}
+
+/// Connection changes reported to the callback set with
+/// [`Client::with_state_listener`](super::Client::with_state_listener).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The connection was lost; reconnecting starts right after
+    Disconnected,
+    /// About to wait `delay` and then make reconnect attempt `attempt`
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Every attempt failed; the next request starts over
+    GaveUp,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Disconnected => write!(f, "disconnected"),
+            ConnectionState::Reconnecting { attempt, delay } => {
+                write!(f, "reconnecting (attempt {} in {:?})", attempt, delay)
+            }
+            ConnectionState::GaveUp => write!(f, "gave up reconnecting"),
+        }
+    }
+}
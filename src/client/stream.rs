@@ -0,0 +1,85 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+};
+
+#[cfg(feature = "tls")]
+use rustls::{ClientConnection, StreamOwned};
+
+/// Connection of a [`Client`](super::Client): plain TCP, or TLS with the
+/// `tls` feature
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    /// The underlying socket
+    pub fn socket(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => &stream.sock,
+        }
+    }
+
+    /// Reads whatever has arrived without blocking: `Ok(None)` if nothing
+    /// has, `Ok(Some(0))` if the peer closed the connection
+    pub fn read_available(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        self.socket().set_nonblocking(true)?;
+        let result = match self {
+            // Leave the bytes for the frame decoder to read properly
+            Stream::Plain(stream) => stream.peek(buf),
+            // Records such as session tickets are consumed by rustls and
+            // never show up as plaintext
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        };
+        self.socket().set_nonblocking(false)?;
+        match result {
+            Ok(n) => Ok(Some(n)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Says goodbye at the TLS level, then closes the socket
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        #[cfg(feature = "tls")]
+        if let Stream::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            // Best effort: the server may already be gone
+            let _ = stream.conn.complete_io(&mut stream.sock);
+        }
+        self.socket().shutdown(Shutdown::Both)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Error type shared by the client and the server.
+
+use crate::{framing::FrameError, message::ErrorCode, server::ConfigError};
+use std::{error, fmt, io, time::Duration};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong talking the framed protocol.
+///
+/// Causes stay typed, so callers match on the variant (or walk
+/// [`source`](std::error::Error::source)) instead of parsing messages.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the socket failed
+    Io(io::Error),
+    /// A frame did not hold a valid protobuf message
+    Decode(prost::DecodeError),
+    /// A frame exceeded the configured maximum size
+    FrameTooLarge { len: usize, max: usize },
+    /// A frame was truncated or its length prefix was malformed
+    Frame(FrameError),
+    /// No data arrived within the configured timeout
+    Timeout,
+    /// The server answered with an `ErrorResponse`
+    Server { code: ErrorCode, detail: String },
+    /// `connect` has not been called, or the connection was lost
+    NotConnected,
+    /// The peer closed the connection
+    Disconnected,
+    /// The server has already been stopped
+    Shutdown,
+    /// The server answered with a different message than the request calls for
+    UnexpectedResponse { expected: &'static str },
+    /// No pooled connection became free within the checkout timeout
+    PoolTimeout { waited: Duration },
+    /// The server configuration is invalid
+    Config(ConfigError),
+}
+
+impl Error {
+    /// True if the connection itself is gone, rather than one request failing
+    pub fn is_connection_lost(&self) -> bool {
+        match self {
+            Error::Disconnected | Error::NotConnected => true,
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::NotConnected
+            ),
+            _ => false,
+        }
+    }
+
+    /// True if no response arrived within the client's timeout
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode message: {}", e),
+            Error::FrameTooLarge { len, max } => write!(
+                f,
+                "frame of {} bytes exceeds the maximum of {} bytes",
+                len, max
+            ),
+            Error::Frame(e) => write!(f, "framing error: {}", e),
+            Error::Timeout => write!(f, "timed out"),
+            Error::Server { code, detail } => {
+                write!(f, "server error {}: {}", code.as_str_name(), detail)
+            }
+            Error::NotConnected => write!(f, "no active connection"),
+            Error::Disconnected => write!(f, "peer closed the connection"),
+            Error::Shutdown => write!(f, "server has been shut down"),
+            Error::UnexpectedResponse { expected } => {
+                write!(f, "unexpected response, expected {}", expected)
+            }
+            Error::PoolTimeout { waited } => {
+                write!(f, "no pooled connection free after {:?}", waited)
+            }
+            Error::Config(e) => write!(f, "invalid configuration: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Frame(e) => Some(e),
+            Error::Config(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            // Sockets with a read timeout report it as either kind
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Error::Timeout,
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::Io(e) => e.into(),
+            FrameError::TooLarge { len, max } => Error::FrameTooLarge { len, max },
+            e => Error::Frame(e),
+        }
+    }
+}
+
+impl From<prost::DecodeError> for Error {
+    fn from(e: prost::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            // Binding the listener is configured by `ServerConfig` but fails
+            // like any other socket operation
+            ConfigError::Io(e) => e.into(),
+            e => Error::Config(e),
+        }
+    }
+}
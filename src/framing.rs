@@ -0,0 +1,228 @@
+//! Length-delimited framing for the `ClientMessage`/`ServerMessage` protocol.
+//!
+//! Every message on the wire is prefixed with its encoded length as a protobuf
+//! varint, which is exactly what `prost::Message::encode_length_delimited`
+//! produces. TCP gives no message boundaries, so the receiving side buffers
+//! incoming bytes in a [`FrameDecoder`] until a whole frame is available.
+
+use prost::Message;
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+/// Default upper bound for a single frame payload (1 MiB).
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// A varint-encoded `u64` never takes more than 10 bytes.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Size of the chunk used when pulling bytes from a socket.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Errors produced while reading or writing frames.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The frame length exceeds the configured maximum.
+    TooLarge { len: usize, max: usize },
+    /// The stream ended in the middle of a frame.
+    Truncated { buffered: usize },
+    /// The length prefix is not a valid varint.
+    InvalidLength,
+    /// The underlying stream failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::TooLarge { len, max } => {
+                write!(
+                    f,
+                    "frame of {} bytes exceeds the maximum of {} bytes",
+                    len, max
+                )
+            }
+            FrameError::Truncated { buffered } => {
+                write!(
+                    f,
+                    "stream closed mid-frame with {} bytes buffered",
+                    buffered
+                )
+            }
+            FrameError::InvalidLength => write!(f, "invalid frame length prefix"),
+            FrameError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FrameError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+impl From<FrameError> for io::Error {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::Io(e) => e,
+            FrameError::Truncated { .. } => io::Error::new(ErrorKind::UnexpectedEof, e),
+            _ => io::Error::new(ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl FrameError {
+    /// Returns true if the error only means "no data yet" on a socket with a
+    /// timeout or in non-blocking mode; the decoder state is still valid.
+    pub fn is_would_block(&self) -> bool {
+        matches!(
+            self,
+            FrameError::Io(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+        )
+    }
+}
+
+/// Encodes `message` as a single length-delimited frame.
+pub fn encode_frame<M: Message>(message: &M, max_frame_size: usize) -> Result<Vec<u8>, FrameError> {
+    let len = message.encoded_len();
+    if len > max_frame_size {
+        return Err(FrameError::TooLarge {
+            len,
+            max: max_frame_size,
+        });
+    }
+    Ok(message.encode_length_delimited_to_vec())
+}
+
+/// Encodes `message` as a frame and writes it to `writer` in one go.
+pub fn write_frame<W: Write, M: Message>(
+    writer: &mut W,
+    message: &M,
+    max_frame_size: usize,
+) -> Result<(), FrameError> {
+    let frame = encode_frame(message, max_frame_size)?;
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Incremental decoder that splits a byte stream into frame payloads.
+///
+/// Bytes may be fed in arbitrary pieces; partially received frames stay
+/// buffered until the rest arrives, so a read timeout never loses data.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        FrameDecoder::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameDecoder {
+    pub fn new(max_frame_size: usize) -> Self {
+        FrameDecoder {
+            buffer: Vec::new(),
+            max_frame_size,
+        }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Number of bytes received but not yet returned as a frame
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Appends raw bytes received from the peer
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete frame payload, or `None` if more bytes are needed.
+    pub fn decode(&mut self) -> Result<Option<Vec<u8>>, FrameError> {
+        // Parse the varint length prefix by hand so that an incomplete prefix
+        // can be told apart from a malformed one.
+        let mut len: u64 = 0;
+        let mut prefix_len = None;
+        for (i, byte) in self.buffer.iter().take(MAX_VARINT_LEN).enumerate() {
+            len |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                prefix_len = Some(i + 1);
+                break;
+            }
+        }
+
+        let prefix_len = match prefix_len {
+            Some(n) => n,
+            None if self.buffer.len() >= MAX_VARINT_LEN => return Err(FrameError::InvalidLength),
+            None => return Ok(None),
+        };
+
+        // Reject oversize frames before buffering their payload
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        if len > self.max_frame_size {
+            return Err(FrameError::TooLarge {
+                len,
+                max: self.max_frame_size,
+            });
+        }
+
+        if self.buffer.len() < prefix_len + len {
+            return Ok(None);
+        }
+
+        let frame = self.buffer[prefix_len..prefix_len + len].to_vec();
+        self.buffer.drain(..prefix_len + len);
+        Ok(Some(frame))
+    }
+
+    /// Reads from `reader` until a whole frame is available.
+    ///
+    /// Returns `Ok(None)` when the peer closed the stream cleanly between
+    /// frames and [`FrameError::Truncated`] when it closed mid-frame.
+    /// `WouldBlock`/`TimedOut` errors are passed through with the decoder
+    /// state intact, so the call can simply be retried.
+    pub fn read_frame<R: Read>(&mut self, reader: &mut R) -> Result<Option<Vec<u8>>, FrameError> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            if let Some(frame) = self.decode()? {
+                return Ok(Some(frame));
+            }
+
+            let bytes_read = match reader.read(&mut chunk) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(FrameError::Io(e)),
+            };
+
+            if bytes_read == 0 {
+                return if self.buffer.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(FrameError::Truncated {
+                        buffered: self.buffer.len(),
+                    })
+                };
+            }
+
+            self.extend(&chunk[..bytes_read]);
+        }
+    }
+}
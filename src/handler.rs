@@ -0,0 +1,313 @@
+//! Request handlers and the registry the server dispatches through.
+//!
+//! Each `client_message::Message` variant is routed to one [`Handler`]. The
+//! server ships with handlers for echo and add; applications can replace
+//! them or register their own without touching the connection code.
+//!
+//! A handler reports failure by returning an `ErrorResponse` message, which
+//! is sent to the client like any other response.
+//!
+//! Handlers that care who sent a request implement
+//! [`Handler::handle_in`], or are built with [`with_context`], to see the
+//! [`ConnectionContext`] of the connection it arrived on.
+
+use crate::message::{
+    client_message, server_message, AddRequest, AddResponse, EchoMessage, ErrorCode, ErrorResponse,
+};
+use log::{error, info, warn};
+use std::{
+    collections::HashMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+pub use self::context::{ConnectionContext, PeerIdentity};
+
+mod context;
+
+/// Identifies a `client_message::Message` variant without its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    Echo,
+    Add,
+    Auth,
+}
+
+impl MessageKind {
+    pub fn of(request: &client_message::Message) -> Self {
+        match request {
+            client_message::Message::EchoMessage(_) => MessageKind::Echo,
+            client_message::Message::AddRequest(_) => MessageKind::Add,
+            client_message::Message::AuthRequest(_) => MessageKind::Auth,
+        }
+    }
+
+    /// True if sending the request twice has the same effect as once, so a
+    /// client may replay it after losing the connection.
+    ///
+    /// Authenticating twice is refused, so `Auth` is not; the client sends
+    /// its token again on each new connection instead.
+    pub fn is_idempotent(self) -> bool {
+        match self {
+            MessageKind::Echo | MessageKind::Add => true,
+            MessageKind::Auth => false,
+        }
+    }
+}
+
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageKind::Echo => write!(f, "echo"),
+            MessageKind::Add => write!(f, "add"),
+            MessageKind::Auth => write!(f, "auth"),
+        }
+    }
+}
+
+/// Turns a request into the response sent back to the client.
+///
+/// Handlers are shared between connection threads, so they must be
+/// `Send + Sync`. Any matching closure is a handler.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: client_message::Message) -> server_message::Message;
+
+    /// Like `handle`, but also sees the connection the request arrived on.
+    /// The default ignores the context.
+    fn handle_in(
+        &self,
+        context: &ConnectionContext,
+        request: client_message::Message,
+    ) -> server_message::Message {
+        let _ = context;
+        self.handle(request)
+    }
+}
+
+impl<F> Handler for F
+where
+    F: Fn(client_message::Message) -> server_message::Message + Send + Sync,
+{
+    fn handle(&self, request: client_message::Message) -> server_message::Message {
+        self(request)
+    }
+}
+
+/// Handler made from a closure that also takes the [`ConnectionContext`].
+pub struct WithContext<F>(F);
+
+/// Turns `f` into a handler that sees the connection of each request
+pub fn with_context<F>(f: F) -> WithContext<F>
+where
+    F: Fn(&ConnectionContext, client_message::Message) -> server_message::Message + Send + Sync,
+{
+    WithContext(f)
+}
+
+impl<F> Handler for WithContext<F>
+where
+    F: Fn(&ConnectionContext, client_message::Message) -> server_message::Message + Send + Sync,
+{
+    fn handle(&self, request: client_message::Message) -> server_message::Message {
+        self.handle_in(&ConnectionContext::default(), request)
+    }
+
+    fn handle_in(
+        &self,
+        context: &ConnectionContext,
+        request: client_message::Message,
+    ) -> server_message::Message {
+        (self.0)(context, request)
+    }
+}
+
+/// Maps message kinds to the handlers that serve them.
+#[derive(Clone)]
+pub struct Router {
+    handlers: HashMap<MessageKind, Arc<dyn Handler>>,
+}
+
+impl Default for Router {
+    /// A router with the built-in echo and add handlers registered
+    fn default() -> Self {
+        Router::empty()
+            .route(MessageKind::Echo, echo)
+            .route(MessageKind::Add, AddHandler::default())
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("kinds", &self.handlers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Router {
+    /// A router with no handlers at all
+    pub fn empty() -> Self {
+        Router {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers `handler` for `kind`, replacing any previous one
+    pub fn route<H: Handler + 'static>(mut self, kind: MessageKind, handler: H) -> Self {
+        self.register(kind, handler);
+        self
+    }
+
+    /// In-place variant of [`Router::route`]
+    pub fn register<H: Handler + 'static>(&mut self, kind: MessageKind, handler: H) {
+        self.handlers.insert(kind, Arc::new(handler));
+    }
+
+    pub fn handles(&self, kind: MessageKind) -> bool {
+        self.handlers.contains_key(&kind)
+    }
+
+    /// Runs the handler registered for the request's kind.
+    ///
+    /// Requests without a handler get an `UnknownVariant` error and a
+    /// panicking handler gets an `Internal` error, so the caller always has
+    /// something to send back.
+    pub fn dispatch(&self, request: client_message::Message) -> server_message::Message {
+        self.dispatch_in(&ConnectionContext::default(), request)
+    }
+
+    /// Like [`Router::dispatch`], for a request that arrived on `context`
+    pub fn dispatch_in(
+        &self,
+        context: &ConnectionContext,
+        request: client_message::Message,
+    ) -> server_message::Message {
+        let kind = MessageKind::of(&request);
+        let handler = match self.handlers.get(&kind) {
+            Some(handler) => handler,
+            None => {
+                return error_response(
+                    ErrorCode::UnknownVariant,
+                    format!("no handler registered for {} requests", kind),
+                )
+            }
+        };
+
+        match panic::catch_unwind(AssertUnwindSafe(|| handler.handle_in(context, request))) {
+            Ok(response) => response,
+            Err(_) => {
+                error!("The {} handler panicked", kind);
+                error_response(ErrorCode::Internal, format!("{} handler failed", kind))
+            }
+        }
+    }
+}
+
+/// Builds the `ErrorResponse` variant of a server message.
+pub fn error_response(code: ErrorCode, detail: impl Into<String>) -> server_message::Message {
+    let mut response = ErrorResponse {
+        detail: detail.into(),
+        ..Default::default()
+    };
+    response.set_code(code);
+    server_message::Message::ErrorResponse(response)
+}
+
+/// Built-in handler that sends an `EchoMessage` straight back.
+pub fn echo(request: client_message::Message) -> server_message::Message {
+    match request {
+        client_message::Message::EchoMessage(EchoMessage { content }) => {
+            info!("Received echo: {}", content);
+            server_message::Message::EchoMessage(EchoMessage { content })
+        }
+        other => unexpected(MessageKind::Echo, other),
+    }
+}
+
+/// What the add handler does when `a + b` does not fit in an `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Reply with an `Overflow` error response
+    #[default]
+    Error,
+    /// Clamp the result to `i32::MIN`/`i32::MAX`
+    Saturate,
+    /// Carry the exact sum in `wide_result`; `result` is saturated
+    Widen,
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowPolicy::Error => write!(f, "error"),
+            OverflowPolicy::Saturate => write!(f, "saturate"),
+            OverflowPolicy::Widen => write!(f, "widen"),
+        }
+    }
+}
+
+/// Built-in handler that answers an `AddRequest` with the sum of its operands.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddHandler {
+    policy: OverflowPolicy,
+}
+
+impl AddHandler {
+    pub fn new(policy: OverflowPolicy) -> Self {
+        AddHandler { policy }
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+}
+
+impl Handler for AddHandler {
+    fn handle(&self, request: client_message::Message) -> server_message::Message {
+        let (a, b) = match request {
+            client_message::Message::AddRequest(AddRequest { a, b }) => (a, b),
+            other => return unexpected(MessageKind::Add, other),
+        };
+        info!("Received add: {} + {}", a, b);
+
+        let response = match (a.checked_add(b), self.policy) {
+            (Some(result), OverflowPolicy::Widen) => AddResponse {
+                result,
+                wide_result: i64::from(result),
+            },
+            (Some(result), _) => AddResponse {
+                result,
+                ..Default::default()
+            },
+            (None, OverflowPolicy::Error) => {
+                warn!("Add overflowed: {} + {}", a, b);
+                return error_response(
+                    ErrorCode::Overflow,
+                    format!("{} + {} does not fit in a 32-bit integer", a, b),
+                );
+            }
+            (None, OverflowPolicy::Saturate) => AddResponse {
+                result: a.saturating_add(b),
+                ..Default::default()
+            },
+            (None, OverflowPolicy::Widen) => AddResponse {
+                result: a.saturating_add(b),
+                wide_result: i64::from(a) + i64::from(b),
+            },
+        };
+        server_message::Message::AddResponse(response)
+    }
+}
+
+// A built-in handler was registered for the wrong kind. That is a mistake in
+// the router setup rather than in the request, hence `Internal`.
+fn unexpected(expected: MessageKind, request: client_message::Message) -> server_message::Message {
+    let detail = format!(
+        "{} handler received a {} request",
+        expected,
+        MessageKind::of(&request)
+    );
+    error!("{}", detail);
+    error_response(ErrorCode::Internal, detail)
+}
@@ -0,0 +1,82 @@
+use std::{fmt, net::SocketAddr, sync::OnceLock};
+
+/// Who is on the other end of a TLS connection, taken from the client
+/// certificate the server verified.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PeerIdentity {
+    /// Distinguished name of the certificate subject, e.g. `CN=device-42, O=Fleet`
+    pub subject: String,
+    /// Common name of the subject, if it has one
+    pub common_name: Option<String>,
+    /// Subject alternative names: DNS names, IP addresses, URIs and emails
+    pub sans: Vec<String>,
+}
+
+impl PeerIdentity {
+    /// The common name if there is one, else the full subject
+    pub fn name(&self) -> &str {
+        self.common_name.as_deref().unwrap_or(&self.subject)
+    }
+}
+
+/// What a handler knows about the connection a request arrived on.
+///
+/// Requests dispatched outside a connection, e.g. straight through
+/// [`Router::dispatch`](super::Router::dispatch), get an empty context.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConnectionContext {
+    peer_addr: Option<SocketAddr>,
+    identity: Option<PeerIdentity>,
+    principal: OnceLock<String>, // Set once the connection authenticates
+}
+
+impl ConnectionContext {
+    pub fn new(peer_addr: SocketAddr) -> Self {
+        ConnectionContext {
+            peer_addr: Some(peer_addr),
+            ..Default::default()
+        }
+    }
+
+    /// Attaches the identity verified during the TLS handshake
+    pub fn with_identity(mut self, identity: PeerIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The verified client identity; `None` unless the server requires
+    /// client certificates
+    pub fn identity(&self) -> Option<&PeerIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Who the connection authenticated as with an `AuthRequest`, if it did
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.get().map(String::as_str)
+    }
+
+    /// Records a successful authentication; false if there already was one
+    pub(crate) fn authenticate(&self, principal: String) -> bool {
+        self.principal.set(principal).is_ok()
+    }
+}
+
+impl fmt::Display for ConnectionContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self
+            .identity
+            .as_ref()
+            .map(PeerIdentity::name)
+            .or(self.principal());
+        match (name, self.peer_addr) {
+            (Some(name), Some(addr)) => write!(f, "{} ({})", name, addr),
+            (Some(name), None) => write!(f, "{}", name),
+            (None, Some(addr)) => write!(f, "{}", addr),
+            (None, None) => write!(f, "unknown peer"),
+        }
+    }
+}
@@ -1,5 +1,13 @@
+pub mod client;
+pub mod error;
+pub mod framing;
+pub mod handler;
 pub mod server;
 
+pub use error::{Error, Result};
+#[cfg(feature = "tls")]
+pub use rustls;
+
 pub mod message {
     include!(concat!(env!("OUT_DIR"), "/messages.rs"));
 }
@@ -1,143 +1,677 @@
-use crate::message::EchoMessage;
-use log::{error, info, warn};
+use self::access::{Access, AccessRules, Slot};
+#[cfg(feature = "tokio")]
+pub use self::async_server::AsyncServer;
+use self::auth::Gate;
+pub use self::auth::{AuthError, Authenticator, StaticTokens};
+pub use self::builder::ServerBuilder;
+use self::clients::ConnectedClients;
+pub use self::config::{ConfigError, ServerConfig, DEFAULT_ADDR, DEFAULT_BACKLOG, ENV_PREFIX};
+pub use self::handle::ServerHandle;
+use self::metrics::Metrics;
+pub use self::metrics::MetricsSnapshot;
+use self::pool::ThreadPool;
+use self::rate_limit::{Limits, RateLimits, Verdict};
+pub use self::shutdown::ShutdownReport;
+use self::shutdown::{Lifecycle, OpenConnections};
+use self::transport::{Acceptor, Reader, Writer};
+use crate::{
+    error::Error,
+    framing::{encode_frame, FrameDecoder, FrameError},
+    handler::{
+        error_response, AddHandler, ConnectionContext, Handler, MessageKind, OverflowPolicy, Router,
+    },
+    message::{client_message, server_message, ClientMessage, ErrorCode, ServerMessage},
+};
+pub use ipnet::IpNet;
+use log::{debug, error, info, warn};
+use mio::Waker;
 use prost::Message;
+use socket2::SockRef;
 use std::{
-    io::{self, ErrorKind, Read, Write},
-    net::{TcpListener, TcpStream},
+    fmt,
+    io::{self, ErrorKind, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        Arc, Condvar, Mutex,
     },
     thread,
     time::Duration,
 };
 
+mod access;
+#[cfg(feature = "tokio")]
+mod async_server;
+mod auth;
+mod builder;
+mod clients;
+mod config;
+mod event_loop;
+mod handle;
+mod metrics;
+mod pool;
+mod rate_limit;
+mod shutdown;
+#[cfg(feature = "tls")]
+mod tls;
+mod transport;
+
+/// Decodes a request frame into its ID and message, or the error response to
+/// send back instead
+fn decode_request(
+    frame: &[u8],
+) -> Result<(u64, client_message::Message), (u64, server_message::Message)> {
+    match ClientMessage::decode(frame) {
+        Ok(ClientMessage {
+            message: Some(request),
+            request_id,
+        }) => Ok((request_id, request)),
+        Ok(ClientMessage {
+            message: None,
+            request_id,
+        }) => {
+            // Also what a message kind unknown to this build decodes to
+            warn!("Received a ClientMessage without a known message");
+            let response = error_response(
+                ErrorCode::UnknownVariant,
+                "ClientMessage carries no known message",
+            );
+            Err((request_id, response))
+        }
+        Err(e) => {
+            error!("Failed to decode message: {}", e);
+            let response = error_response(
+                ErrorCode::DecodeFailure,
+                format!("failed to decode ClientMessage: {}", e),
+            );
+            Err((0, response))
+        }
+    }
+}
+
+/// Checks a connection against the access rules right after `accept`;
+/// refused ones are counted and logged, and closed once the caller drops
+/// the stream
+fn screen(access: &Arc<Access>, metrics: &Metrics, addr: SocketAddr) -> Option<Slot> {
+    match access.admit(addr.ip()) {
+        Ok(slot) => Some(slot),
+        Err(refusal) => {
+            metrics.connection_rejected(refusal.is_denied());
+            warn!("Refused client {}: {}", addr, refusal);
+            None
+        }
+    }
+}
+
+/// Just the request ID of a `ClientMessage`; decoding it skips over the
+/// message itself
+#[derive(Clone, PartialEq, Message)]
+struct RequestId {
+    #[prost(uint64, tag = "15")]
+    request_id: u64,
+}
+
+/// What becomes of a request frame once it has been through [`Admission`];
+/// each variant carries the request ID to answer with
+enum Admit {
+    /// Hand the decoded request to the router
+    Serve(u64, client_message::Message),
+    /// Send this instead of serving it
+    Answer(u64, server_message::Message),
+    /// Send this, then close the connection
+    Disconnect(u64, server_message::Message),
+}
+
+/// Checks every frame of a connection against the rate limits, decodes it
+/// and passes it through the authentication gate before it may reach the
+/// router
+struct Admission {
+    limiter: rate_limit::RateLimiter,
+    gate: Option<Gate>,
+}
+
+impl Admission {
+    fn new(limits: &Arc<RateLimits>, gate: Option<&Gate>, ip: IpAddr) -> Self {
+        Admission {
+            limiter: limits.limiter(ip),
+            gate: gate.cloned(),
+        }
+    }
+
+    /// Rate limits come first, so that undecodable frames and token
+    /// guesses are throttled like any other request
+    fn admit(&mut self, context: &ConnectionContext, frame: &[u8]) -> Admit {
+        match self.limiter.check(frame.len()) {
+            Verdict::Allow => {}
+            Verdict::Refuse(response) => {
+                debug!("Rate limited client {}", context);
+                return Admit::Answer(peek_request_id(frame), response);
+            }
+            Verdict::Disconnect(response) => {
+                warn!("Disconnecting client {}: rate limited too often", context);
+                return Admit::Disconnect(peek_request_id(frame), response);
+            }
+        }
+        let (request_id, request) = match decode_request(frame) {
+            Ok(request) => request,
+            Err((request_id, response)) => return Admit::Answer(request_id, response),
+        };
+        match self.gate {
+            Some(ref gate) => match gate.check(context, request) {
+                Ok(request) => Admit::Serve(request_id, request),
+                Err(response) => Admit::Answer(request_id, response),
+            },
+            None => Admit::Serve(request_id, request),
+        }
+    }
+}
+
+/// Request ID of a frame that is not decoded in full; 0 if there is none
+fn peek_request_id(frame: &[u8]) -> u64 {
+    RequestId::decode(frame).map_or(0, |id| id.request_id)
+}
+
+/// Encodes a response frame, substituting an error if it would be too large
+fn encode_response(
+    request_id: u64,
+    message: server_message::Message,
+    max_frame_size: usize,
+) -> Vec<u8> {
+    let mut response = ServerMessage {
+        message: Some(message),
+        request_id,
+    };
+    match encode_frame(&response, max_frame_size) {
+        Ok(frame) => frame,
+        Err(e) => {
+            error!("Response cannot be sent: {}", e);
+            response.message = Some(error_response(
+                ErrorCode::Internal,
+                format!("response too large: {}", e),
+            ));
+            response.encode_length_delimited_to_vec()
+        }
+    }
+}
+
+/// Frame errors after which the client is told why it is being dropped
+fn frame_error_response(e: &FrameError) -> Option<server_message::Message> {
+    match e {
+        FrameError::TooLarge { .. } | FrameError::InvalidLength => {
+            Some(error_response(ErrorCode::DecodeFailure, e.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Runs `request` through the router, logging both ends if asked to
+fn dispatch(
+    router: &Router,
+    context: &ConnectionContext,
+    request_id: u64,
+    request: client_message::Message,
+    log_payloads: bool,
+) -> server_message::Message {
+    if log_payloads {
+        info!("Request {}: {:?}", request_id, request);
+    }
+    let response = router.dispatch_in(context, request);
+    if log_payloads {
+        info!("Response {}: {:?}", request_id, response);
+    }
+    response
+}
+
+/// Write half of a connection, shared by the jobs answering its requests
+struct Responder {
+    stream: Mutex<Writer>,
+    socket: TcpStream, // For tearing the connection down
+    max_frame_size: usize,
+}
+
+impl Responder {
+    fn send(&self, request_id: u64, message: server_message::Message) -> io::Result<()> {
+        let frame = encode_response(request_id, message, self.max_frame_size);
+        // Hold the lock for the whole frame so responses never interleave
+        let mut stream = self.stream.lock().unwrap();
+        stream
+            .write_all(&frame)
+            .and_then(|_| stream.flush())
+            .map_err(|e| {
+                error!("Failed to send response: {}", e);
+                e
+            })
+    }
+
+    /// Tears the connection down so the reader notices a failed write
+    fn close(&self) {
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+}
+
+/// Number of a connection's requests that are queued or being processed
+struct InFlight {
+    count: Mutex<usize>,
+    changed: Condvar,
+    limit: usize,
+}
+
+impl InFlight {
+    fn new(limit: usize) -> Self {
+        InFlight {
+            count: Mutex::new(0),
+            changed: Condvar::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Blocks while the connection is at its limit, then takes a slot
+    fn acquire(self: &Arc<Self>) -> InFlightPermit {
+        let mut count = self.count.lock().unwrap();
+        while *count >= self.limit {
+            count = self.changed.wait(count).unwrap();
+        }
+        *count += 1;
+        InFlightPermit(Arc::clone(self))
+    }
+
+    /// Blocks until every request has been answered
+    fn wait_idle(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.changed.wait(count).unwrap();
+        }
+    }
+}
+
+/// Slot in [`InFlight`], released when the request's job is done
+struct InFlightPermit(Arc<InFlight>);
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= 1;
+        self.0.changed.notify_all();
+    }
+}
+
 struct Client {
-    stream: TcpStream,
+    context: Arc<ConnectionContext>,
+    stream: Reader,
+    frames: FrameDecoder,
+    router: Arc<Router>,
+    admission: Admission,
+    workers: Arc<ThreadPool>,
+    responder: Arc<Responder>,
+    in_flight: Arc<InFlight>,
+    log_payloads: bool,
 }
 
 impl Client {
-    pub fn new(mut stream: TcpStream) -> Self {
-        Client { stream }
+    pub fn new(
+        stream: TcpStream,
+        addr: SocketAddr,
+        config: &ServerConfig,
+        acceptor: &Acceptor,
+        router: Arc<Router>,
+        admission: Admission,
+        workers: Arc<ThreadPool>,
+    ) -> io::Result<Self> {
+        // Accepted sockets may inherit non-blocking mode from the listener
+        stream.set_nonblocking(false)?;
+        config.configure(SockRef::from(&stream))?;
+
+        let (reader, writer, context) = acceptor.split(&stream, addr)?;
+        let responder = Responder {
+            stream: Mutex::new(writer),
+            socket: stream,
+            max_frame_size: config.max_frame_size,
+        };
+        Ok(Client {
+            context: Arc::new(context),
+            stream: reader,
+            frames: FrameDecoder::new(config.max_frame_size),
+            router,
+            admission,
+            workers,
+            responder: Arc::new(responder),
+            in_flight: Arc::new(InFlight::new(config.max_in_flight)),
+            log_payloads: config.log_payloads,
+        })
+    }
+
+    pub fn handle(&mut self) -> crate::Result<()> {
+        let result = self.read_requests();
+        // Let the requests already handed to the workers finish their replies
+        self.in_flight.wait_idle();
+        result
     }
 
-    pub fn handle(&mut self) -> io::Result<()> {
-        let mut buffer = [0; 512];
-    
+    fn read_requests(&mut self) -> crate::Result<()> {
         loop {
-            // Read data from the client
-            let bytes_read = match self.stream.read(&mut buffer) {
-                Ok(bytes) => bytes,
-                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                    // Non-blocking mode: no data available
-                    thread::sleep(Duration::from_millis(10));
-                    continue;
+            // Read the next complete frame from the client
+            let frame = match self.frames.read_frame(&mut self.stream) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    // The client closed the connection between frames
+                    info!("Client disconnected.");
+                    return Ok(());
                 }
-                Err(e) => {
+                Err(ref e) if e.is_would_block() => {
+                    // The socket blocks, so this is the read timeout expiring
+                    warn!("Client sent nothing within the read timeout");
+                    return Err(Error::Timeout);
+                }
+                Err(FrameError::Io(e)) => {
                     error!("Error reading from client: {}", e);
-                    return Err(e);
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    // Oversize or malformed frames leave the stream out of sync,
+                    // so report the problem and drop the connection
+                    error!("Error reading frame from client: {}", e);
+                    if let Some(response) = frame_error_response(&e) {
+                        // Best effort: the client may already be gone
+                        let _ = self.responder.send(0, response);
+                    }
+                    return Err(e.into());
                 }
             };
-    
-            // If no bytes are read, the client has disconnected
-            if bytes_read == 0 {
-                info!("Client disconnected.");
-                return Ok(());
-            }
-    
-            // Decode and process the received message
-            if let Ok(message) = EchoMessage::decode(&buffer[..bytes_read]) {
-                info!("Received: {}", message.content);
-    
-                // Echo back the message
-                let payload = message.encode_to_vec();
-                if let Err(e) = self.stream.write_all(&payload) {
-                    error!("Failed to send response: {}", e);
-                    return Err(e);
+
+            // Only well-formed requests go to the workers. Answered here, so
+            // a request after an `AuthRequest` is only read once the
+            // authentication is settled
+            let (request_id, request) = match self.admission.admit(&self.context, &frame) {
+                Admit::Serve(request_id, request) => (request_id, request),
+                Admit::Answer(request_id, response) => {
+                    self.responder.send(request_id, response)?;
+                    continue;
                 }
-    
-                // Flush the stream to ensure data is sent immediately
-                if let Err(e) = self.stream.flush() {
-                    error!("Failed to flush stream: {}", e);
-                    return Err(e);
+                Admit::Disconnect(request_id, response) => {
+                    let _ = self.responder.send(request_id, response);
+                    return Ok(());
                 }
-            } else {
-                error!("Failed to decode message");
-            }
+            };
+
+            // Waits here while the connection already has too many requests
+            // in flight, which also stops reading from the socket
+            let permit = self.in_flight.acquire();
+            let context = Arc::clone(&self.context);
+            let router = Arc::clone(&self.router);
+            let responder = Arc::clone(&self.responder);
+            let log_payloads = self.log_payloads;
+            self.workers.execute(move || {
+                let response = dispatch(&router, &context, request_id, request, log_payloads);
+                if responder.send(request_id, response).is_err() {
+                    responder.close();
+                }
+                drop(permit);
+            });
         }
-    }    
+    }
 }
 
 pub struct Server {
     listener: TcpListener,
     is_running: Arc<AtomicBool>,
     port: u16, // Store the dynamically assigned port
-    clients: Arc<Mutex<Vec<String>>>, // Track connected clients safely
+    clients: Arc<ConnectedClients>,
+    config: ServerConfig,
+    router: Arc<Router>, // Handlers shared by all client threads
+    gate: Option<Gate>,
+    limits: Arc<RateLimits>,
+    access: Arc<Access>,
+    metrics: Arc<Metrics>,
+    waker: Mutex<Option<Arc<Waker>>>, // Set while the event loop runs
+    lifecycle: Lifecycle,
+    acceptor: Acceptor,
+}
+
+/// How the server waits for and services connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// One pooled thread per connection, blocking on its socket
+    #[default]
+    Threaded,
+    /// A single thread multiplexing every connection with epoll (via mio);
+    /// handlers still run on the worker pool
+    EventLoop,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Threaded => write!(f, "threaded"),
+            Backend::EventLoop => write!(f, "event_loop"),
+        }
+    }
 }
 
+/// Requests a single connection may have queued or in progress by default
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// Connections served at the same time by default
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Accepted connections that may wait for a connection thread by default
+pub const DEFAULT_ACCEPT_QUEUE: usize = 64;
+
+/// How long `stop` lets open connections finish their requests by default
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 impl Server {
     /// Creates a new server instance
-    pub fn new(addr: &str) -> io::Result<Self> {
-        let listener = TcpListener::bind(addr)?;
+    pub fn new(addr: &str) -> crate::Result<Self> {
+        Ok(Self::bind(
+            ServerConfig::new(addr),
+            Router::default(),
+            None,
+        )?)
+    }
+
+    /// Starts configuring a server; see [`ServerBuilder`]
+    pub fn builder(addr: &str) -> ServerBuilder {
+        ServerBuilder::new(addr)
+    }
+
+    /// Binds the listener; without an explicit `gate`, one is loaded from
+    /// the configured token file if there is one
+    fn bind(config: ServerConfig, router: Router, gate: Option<Gate>) -> Result<Self, ConfigError> {
+        let acceptor = Acceptor::new(&config)?;
+        let gate = match (gate, &config.auth_tokens) {
+            (Some(gate), _) => Some(gate),
+            (None, Some(path)) => Some(Gate::new(StaticTokens::from_file(path)?)),
+            (None, None) => None,
+        };
+        let limits = Arc::new(RateLimits::new(Limits::of(&config)));
+        let access = Arc::new(Access::new(AccessRules::of(&config)));
+        let listener = config.bind()?;
 
         let local_addr = listener.local_addr()?;
         let port = local_addr.port();
         println!("Server is running on: {}", local_addr);
 
-        let is_running = Arc::new(AtomicBool::new(false));
-        let clients = Arc::new(Mutex::new(Vec::new())); // Initializing the client list
+        // Armed at construction so that a `stop` racing ahead of `run` is not lost
+        let is_running = Arc::new(AtomicBool::new(true));
 
         Ok(Server {
             listener,
             is_running,
             port,
-            clients,
+            clients: Arc::default(),
+            config,
+            router: Arc::new(router),
+            gate,
+            limits,
+            access,
+            metrics: Arc::new(Metrics::default()),
+            waker: Mutex::new(None),
+            lifecycle: Lifecycle::new(),
+            acceptor,
         })
     }
 
+    /// Replaces the whole handler registry. Add requests, if the router
+    /// serves them, are answered by the built-in add handler with the
+    /// configured overflow policy; register a custom one with `with_handler`
+    /// afterwards
+    pub fn with_router(mut self, router: Router) -> Self {
+        self.router = Arc::new(router);
+        if self.router.handles(MessageKind::Add) {
+            let policy = self.config.overflow_policy;
+            return self.with_handler(MessageKind::Add, AddHandler::new(policy));
+        }
+        self
+    }
+
+    /// Registers `handler` for `kind`, replacing the built-in one if any
+    pub fn with_handler<H: Handler + 'static>(mut self, kind: MessageKind, handler: H) -> Self {
+        Arc::make_mut(&mut self.router).register(kind, handler);
+        self
+    }
+
+    /// Requires connections to authenticate with a token `authenticator`
+    /// accepts before anything else is served
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.gate = Some(Gate::new(authenticator));
+        self
+    }
+
+    /// Selects how add requests whose sum overflows an `i32` are answered.
+    ///
+    /// This installs a built-in add handler with the given policy, replacing
+    /// any add handler registered before.
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = policy;
+        self.with_handler(MessageKind::Add, AddHandler::new(policy))
+    }
+
+    /// The configuration the server was built with
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Current connection counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
     // Getter to retrieve the dynamically assigned port
     pub fn get_port(&self) -> u16 {
         self.port
     }
 
-    /// Runs the server, listening for incoming connections and handling them
-    pub fn run(&self) -> io::Result<()> {
-        self.is_running.store(true, Ordering::SeqCst); // Set the server as running
+    /// Runs the server on a background thread.
+    ///
+    /// The returned handle stops the server when dropped.
+    pub fn spawn(self) -> crate::Result<ServerHandle> {
+        ServerHandle::spawn(self)
+    }
+
+    /// Runs the server, listening for incoming connections and handling them.
+    ///
+    /// Returns once [`Server::stop`] has been called and the open
+    /// connections have been drained. A server runs only once; running it
+    /// again fails with [`Error::Shutdown`]. Failing to start its worker
+    /// threads is reported as [`Error::Io`].
+    pub fn run(&self) -> crate::Result<()> {
+        if !self.lifecycle.start() {
+            return Err(Error::Shutdown);
+        }
         info!("Server is running on {}", self.listener.local_addr()?);
-    
+
         // Set the listener to non-blocking mode
         self.listener.set_nonblocking(true)?;
-    
+
+        // Requests from every connection are processed on one shared pool
+        let result = ThreadPool::new("worker", self.config.workers).and_then(|workers| {
+            let workers = Arc::new(workers);
+            match self.config.backend {
+                Backend::Threaded => self.run_threaded(workers),
+                Backend::EventLoop => event_loop::run(self, workers),
+            }
+        });
+
+        // Release `stop` even if the backend failed
+        let report = result.as_ref().copied().unwrap_or_default();
+        info!(
+            "Server stopped: {} connections drained, {} closed at the deadline.",
+            report.drained, report.forced
+        );
+        self.lifecycle.finish(report);
+        result.map(|_| ()).map_err(Error::from)
+    }
+
+    /// Accept loop of [`Backend::Threaded`]
+    fn run_threaded(&self, workers: Arc<ThreadPool>) -> io::Result<ShutdownReport> {
+        // Each served connection occupies one of these threads while it is open
+        let connections = ThreadPool::bounded(
+            "connection",
+            self.config.max_connections,
+            self.config.accept_queue,
+        )?;
+        let config = Arc::new(self.config.clone());
+        let open = Arc::new(OpenConnections::default());
+
         while self.is_running.load(Ordering::SeqCst) {
             match self.listener.accept() {
                 Ok((stream, addr)) => {
                     info!("New client connected: {}", addr);
+                    let Some(slot) = screen(&self.access, &self.metrics, addr) else {
+                        continue;
+                    };
 
-                    // Add the client to the list safely
-                    let mut clients = self.clients.lock().unwrap();
-                    clients.push(addr.to_string());
-                    info!("Connected clients: {:?}", clients);
+                    // Tracked from here on, so that queued connections are
+                    // drained on shutdown as well
+                    let registration = match open.register(&stream) {
+                        Ok(registration) => registration,
+                        Err(e) => {
+                            error!("Failed to track client {}: {}", addr, e);
+                            continue;
+                        }
+                    };
 
-                    // Spawn a new thread for each client
-                    let is_running = Arc::clone(&self.is_running);
+                    // Hand the connection to the pool; it waits in the accept
+                    // queue if every connection thread is busy
                     let clients = Arc::clone(&self.clients);
-                    thread::spawn(move || {
-                        let mut client = Client::new(stream);
-                        while is_running.load(Ordering::SeqCst) {
-                            if let Err(e) = client.handle() {
-                                error!("Error handling client {}: {}", addr, e);
-                                break;
-                            }
-                        }
-                        info!("Client {} disconnected.", addr);
+                    let metrics = Arc::clone(&self.metrics);
+                    let config = Arc::clone(&config);
+                    let router = Arc::clone(&self.router);
+                    let admission = Admission::new(&self.limits, self.gate.as_ref(), addr.ip());
+                    let workers = Arc::clone(&workers);
+                    let acceptor = self.acceptor.clone();
+                    self.metrics.connection_queued();
+                    let queued = connections.try_execute(move || {
+                        metrics.connection_started();
 
-                        // Remove the client from the list safely after disconnection
-                        let mut clients = clients.lock().unwrap();
-                        if let Some(pos) = clients.iter().position(|x| x == &addr.to_string()) {
-                            clients.remove(pos);
+                        // Listed once the handshake told us who the client is;
+                        // `handle` only returns once the client is gone
+                        let result = Client::new(
+                            stream, addr, &config, &acceptor, router, admission, workers,
+                        )
+                        .map_err(Error::from)
+                        .and_then(|mut client| {
+                            clients.add(&client.context);
+                            let result = client.handle();
+                            clients.remove(&client.context);
+                            result
+                        });
+                        if let Err(e) = result {
+                            error!("Error handling client {}: {}", addr, e);
                         }
-                        info!("Updated connected clients: {:?}", clients);
+                        info!("Client {} disconnected.", addr);
+                        metrics.connection_finished();
+                        drop(registration);
+                        drop(slot);
                     });
+
+                    if !queued {
+                        // The rejected job owned the stream, so it is closed already
+                        self.metrics.connection_refused();
+                        warn!(
+                            "Refused client {}: all {} connection threads busy and accept queue full",
+                            addr, self.config.max_connections
+                        );
+                    }
                 }
                 Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
                     // No incoming connections, sleep briefly to reduce CPU usage
@@ -148,23 +682,27 @@ impl Server {
                 }
             }
         }
-    
-        info!("Server stopped.");
-        Ok(())
+
+        Ok(open.drain(self.config.shutdown_timeout))
     }
 
-    /// Stops the server by setting the `is_running` flag to `false`
-    pub fn stop(&self) {
-        if self.is_running.load(Ordering::SeqCst) {
-            self.is_running.store(false, Ordering::SeqCst);
-            // Trigger a shutdown signal to unblock accept()
-            if let Ok(_) = TcpStream::connect(format!("127.0.0.1:{}", self.port)) {
-                info!("Shutdown signal sent to unblock listener.");
-            } else {
-                error!("Failed to send shutdown signal.");
+    /// Stops accepting connections and drains the open ones.
+    ///
+    /// Open connections stop reading new requests but still answer the ones
+    /// they have, for up to the shutdown timeout; connections busy beyond
+    /// that are closed. Blocks until `run` is done and reports the outcome.
+    pub fn stop(&self) -> ShutdownReport {
+        if self.is_running.swap(false, Ordering::SeqCst) {
+            // The event loop blocks in poll and needs waking; the threaded
+            // accept loop notices the flag on its next pass
+            if let Some(ref waker) = *self.waker.lock().unwrap() {
+                if let Err(e) = waker.wake() {
+                    error!("Failed to wake the event loop: {}", e);
+                }
             }
         } else {
             warn!("Server was already stopped or not running.");
         }
+        self.lifecycle.wait()
     }
 }
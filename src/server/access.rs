@@ -0,0 +1,122 @@
+//! Which connections are let in at all.
+//!
+//! Checked right after `accept`, before a connection costs a thread or a
+//! TLS handshake: the deny list, then the allow list, then how many
+//! connections the address already has open.
+
+use super::ServerConfig;
+use ipnet::IpNet;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Allow and deny lists and the per-address cap, as set in the
+/// [`ServerConfig`]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AccessRules {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+    pub max_per_ip: Option<usize>,
+}
+
+impl AccessRules {
+    pub fn of(config: &ServerConfig) -> Self {
+        AccessRules {
+            allow: config.allow.clone(),
+            deny: config.deny.clone(),
+            max_per_ip: config.max_connections_per_ip,
+        }
+    }
+}
+
+/// Why a connection was closed right after `accept`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Refusal {
+    /// The address is in this network of the deny list
+    Denied(IpNet),
+    /// There is an allow list and the address is in none of its networks
+    NotAllowed,
+    /// The address already has this many connections open
+    TooMany(usize),
+}
+
+impl Refusal {
+    /// True if the lists refused the address, rather than a connection cap
+    pub fn is_denied(&self) -> bool {
+        !matches!(self, Refusal::TooMany(_))
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::Denied(net) => write!(f, "address in denied network {}", net),
+            Refusal::NotAllowed => write!(f, "address not in an allowed network"),
+            Refusal::TooMany(open) => write!(f, "already {} connections from this address", open),
+        }
+    }
+}
+
+/// Rules shared by every connection of a server, with the open connections
+/// per address
+#[derive(Debug, Default)]
+pub(crate) struct Access {
+    rules: AccessRules,
+    open: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl Access {
+    pub fn new(rules: AccessRules) -> Self {
+        Access {
+            rules,
+            open: Mutex::default(),
+        }
+    }
+
+    /// Lets a connection from `ip` in, or says why not; the connection
+    /// counts against the per-address cap until the [`Slot`] is dropped
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<Slot, Refusal> {
+        // IPv4 clients of a dual-stack listener show up as mapped addresses
+        let ip = ip.to_canonical();
+        if let Some(net) = self.rules.deny.iter().find(|net| net.contains(&ip)) {
+            return Err(Refusal::Denied(*net));
+        }
+        if !self.rules.allow.is_empty() && !self.rules.allow.iter().any(|net| net.contains(&ip)) {
+            return Err(Refusal::NotAllowed);
+        }
+
+        let Some(max) = self.rules.max_per_ip else {
+            return Ok(Slot(None));
+        };
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(ip).or_default();
+        if *count >= max {
+            return Err(Refusal::TooMany(*count));
+        }
+        *count += 1;
+        Ok(Slot(Some((Arc::clone(self), ip))))
+    }
+}
+
+/// Place of an admitted connection under the per-address cap; freed when
+/// dropped
+#[derive(Debug)]
+pub(crate) struct Slot(Option<(Arc<Access>, IpAddr)>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let Some((ref access, ip)) = self.0 else {
+            return;
+        };
+        let mut open = access.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&ip);
+            }
+        }
+    }
+}
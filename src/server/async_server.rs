@@ -0,0 +1,436 @@
+//! Tokio flavour of [`Server`](super::Server), enabled by the `tokio` feature.
+//!
+//! It speaks the same framed protocol and dispatches through the same
+//! [`Router`], so handlers work unchanged on either server. Handlers are
+//! synchronous, so each request runs on tokio's blocking pool.
+//!
+//! Stopping drains like the other backends: connections stop reading, get
+//! until the shutdown timeout to answer what they already read, and are
+//! aborted after that.
+
+use super::{
+    access::{Access, AccessRules, Slot},
+    auth::Gate,
+    clients::ConnectedClients,
+    encode_response, frame_error_response,
+    metrics::Metrics,
+    rate_limit::{Limits, RateLimits},
+    screen,
+    shutdown::Phase,
+    Admission, Admit, Authenticator, ConfigError, MetricsSnapshot, ServerConfig, ShutdownReport,
+    StaticTokens,
+};
+use crate::{
+    error::{Error, Result},
+    framing::FrameDecoder,
+    handler::{
+        error_response, AddHandler, ConnectionContext, Handler, MessageKind, OverflowPolicy, Router,
+    },
+    message::ErrorCode,
+};
+use log::{error, info, warn};
+use socket2::SockRef;
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch, OwnedSemaphorePermit, Semaphore},
+    task::{self, JoinHandle, JoinSet},
+    time,
+};
+
+/// Size of the chunk used when pulling bytes from a socket
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// How many responses a connection may have waiting for its writer before
+/// it stops reading requests
+const OUTGOING_FRAMES: usize = 4;
+
+/// Settings shared by every connection task
+struct Context {
+    router: Arc<Router>,
+    gate: Option<Gate>,
+    limits: Arc<RateLimits>,
+    max_frame_size: usize,
+    max_in_flight: usize,
+}
+
+pub struct AsyncServer {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    phase: watch::Sender<Phase>, // Hands the report of `run` to `stop`
+    clients: Arc<ConnectedClients>,
+    metrics: Arc<Metrics>,
+    config: ServerConfig,
+    router: Arc<Router>,
+    gate: Option<Gate>,
+}
+
+impl AsyncServer {
+    /// Binds a new server instance to `addr` with the default configuration
+    pub async fn bind(addr: &str) -> Result<Self> {
+        Ok(Self::from_config(ServerConfig::new(addr)).await?)
+    }
+
+    /// Validates `config` and binds a server to its address.
+    ///
+    /// This is the same configuration [`Server`](super::Server) takes.
+    /// Connections beyond `max_connections` are closed right after
+    /// `accept`. `backend`, `workers` and `accept_queue` have no meaning
+    /// here, and the read and write timeouts are not enforced. TLS is not
+    /// supported.
+    pub async fn from_config(config: ServerConfig) -> std::result::Result<Self, ConfigError> {
+        config.validate()?;
+        if config.tls_cert.is_some() {
+            return Err(ConfigError::Invalid {
+                key: "tls_cert",
+                reason: "the async server does not support TLS",
+            });
+        }
+        let gate = match config.auth_tokens {
+            Some(ref path) => Some(Gate::new(StaticTokens::from_file(path)?)),
+            None => None,
+        };
+        let router =
+            Router::default().route(MessageKind::Add, AddHandler::new(config.overflow_policy));
+
+        let listener = config.bind()?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let local_addr = listener.local_addr()?;
+        info!("Async server bound to {}", local_addr);
+
+        Ok(AsyncServer {
+            listener,
+            local_addr,
+            shutdown: watch::channel(false).0,
+            phase: watch::channel(Phase::Idle).0,
+            clients: Arc::default(),
+            metrics: Arc::new(Metrics::default()),
+            config,
+            router: Arc::new(router),
+            gate,
+        })
+    }
+
+    /// Replaces the whole handler registry. Add requests, if the router
+    /// serves them, are answered by the built-in add handler with the
+    /// configured overflow policy; register a custom one with `with_handler`
+    /// afterwards
+    pub fn with_router(mut self, router: Router) -> Self {
+        self.router = Arc::new(router);
+        if self.router.handles(MessageKind::Add) {
+            let policy = self.config.overflow_policy;
+            return self.with_handler(MessageKind::Add, AddHandler::new(policy));
+        }
+        self
+    }
+
+    /// Registers `handler` for `kind`, replacing the built-in one if any
+    pub fn with_handler<H: Handler + 'static>(mut self, kind: MessageKind, handler: H) -> Self {
+        Arc::make_mut(&mut self.router).register(kind, handler);
+        self
+    }
+
+    /// Selects how add requests whose sum overflows an `i32` are answered
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = policy;
+        self.with_handler(MessageKind::Add, AddHandler::new(policy))
+    }
+
+    /// Requires connections to authenticate with a token `authenticator`
+    /// accepts before anything else is served
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.gate = Some(Gate::new(authenticator));
+        self
+    }
+
+    /// The configuration the server was bound with
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.local_addr.port()
+    }
+
+    /// Current connection counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Accepts and serves connections until [`AsyncServer::stop`] is called.
+    ///
+    /// Returns once the open connections have been drained. A server runs
+    /// only once; running it again fails with [`Error::Shutdown`].
+    pub async fn run(&self) -> Result<ShutdownReport> {
+        let started = self.phase.send_if_modified(|phase| match phase {
+            Phase::Finished(_) => false,
+            _ => {
+                *phase = Phase::Running;
+                true
+            }
+        });
+        if !started {
+            return Err(Error::Shutdown);
+        }
+        info!("Async server is running on {}", self.local_addr);
+        let mut shutdown = self.shutdown.subscribe();
+        let ctx = Arc::new(Context {
+            router: Arc::clone(&self.router),
+            gate: self.gate.clone(),
+            limits: Arc::new(RateLimits::new(Limits::of(&self.config))),
+            max_frame_size: self.config.max_frame_size,
+            max_in_flight: self.config.max_in_flight,
+        });
+        let access = Arc::new(Access::new(AccessRules::of(&self.config)));
+        let permits = self.config.max_connections.min(Semaphore::MAX_PERMITS);
+        let connections = Arc::new(Semaphore::new(permits));
+        let mut tasks = JoinSet::new();
+
+        // `stop` may have been called before `run`; the flag is sticky
+        while !*shutdown.borrow_and_update() {
+            let (stream, addr) = tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Error accepting connection: {}", e);
+                        continue;
+                    }
+                },
+                _ = shutdown.changed() => break,
+            };
+            info!("New client connected: {}", addr);
+            let Some(slot) = screen(&access, &self.metrics, addr) else {
+                continue;
+            };
+            let Ok(permit) = Arc::clone(&connections).try_acquire_owned() else {
+                self.metrics.connection_rejected(false);
+                warn!(
+                    "Refused client {}: already serving {} connections",
+                    addr, self.config.max_connections
+                );
+                continue;
+            };
+            if let Err(e) = self.config.configure(SockRef::from(&stream)) {
+                // The connection still works, just without the tuning
+                warn!("Failed to set socket options for {}: {}", addr, e);
+            }
+
+            self.metrics.connection_queued();
+            let served = Served {
+                context: Arc::new(ConnectionContext::new(addr)),
+                clients: Arc::clone(&self.clients),
+                metrics: Arc::clone(&self.metrics),
+                _slot: slot,
+                _permit: permit,
+            };
+            let ctx = Arc::clone(&ctx);
+            let shutdown = self.shutdown.subscribe();
+            // Finished connections are reaped here so the set stays small
+            while tasks.try_join_next().is_some() {}
+            tasks.spawn(async move {
+                served.metrics.connection_started();
+                served.clients.add(&served.context);
+                if let Err(e) = serve(stream, Arc::clone(&served.context), ctx, shutdown).await {
+                    error!("Error handling client {}: {}", addr, e);
+                }
+            });
+        }
+
+        let report = drain(tasks, self.config.shutdown_timeout).await;
+        info!(
+            "Async server stopped: {} connections drained, {} closed at the deadline.",
+            report.drained, report.forced
+        );
+        self.phase.send_replace(Phase::Finished(report));
+        Ok(report)
+    }
+
+    /// Makes `run` stop accepting and drain the open connections, and
+    /// waits for it to finish; safe to call before `run` or more than once
+    pub async fn stop(&self) -> ShutdownReport {
+        if self.shutdown.send_replace(true) {
+            warn!("Server was already stopped.");
+        }
+        let mut phase = self.phase.subscribe();
+        let phase = phase
+            .wait_for(|phase| !matches!(phase, Phase::Running))
+            .await
+            .map_or(Phase::Idle, |phase| *phase);
+        match phase {
+            Phase::Finished(report) => report,
+            // Never run, so there is nothing to drain
+            _ => ShutdownReport::default(),
+        }
+    }
+}
+
+/// Waits up to `timeout` for the connection tasks, which stop reading on
+/// shutdown, then aborts the ones still busy
+async fn drain(mut tasks: JoinSet<()>, timeout: Duration) -> ShutdownReport {
+    while tasks.try_join_next().is_some() {}
+    let open = tasks.len();
+    let deadline = time::sleep(timeout);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            joined = tasks.join_next() => if joined.is_none() {
+                break;
+            },
+            _ = &mut deadline => break,
+        }
+    }
+
+    let forced = tasks.len();
+    if forced > 0 {
+        warn!("Closing {} connections still busy at the deadline", forced);
+        tasks.shutdown().await;
+    }
+    ShutdownReport {
+        drained: open - forced,
+        forced,
+    }
+}
+
+/// A connection being served; undoes its bookkeeping when the task ends,
+/// even if it is aborted at the shutdown deadline
+struct Served {
+    context: Arc<ConnectionContext>,
+    clients: Arc<ConnectedClients>,
+    metrics: Arc<Metrics>,
+    _slot: Slot,
+    _permit: OwnedSemaphorePermit, // Counts against `max_connections`
+}
+
+impl Drop for Served {
+    fn drop(&mut self) {
+        info!("Client {} disconnected.", self.context);
+        self.clients.remove(&self.context);
+        self.metrics.connection_finished();
+    }
+}
+
+/// Aborts the task when dropped, so that an aborted connection takes its
+/// writer and request tasks along
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Serves one connection: reads frames, answers each request from its own
+/// task and writes responses as they complete
+async fn serve(
+    stream: TcpStream,
+    context: Arc<ConnectionContext>,
+    ctx: Arc<Context>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let (mut reader, mut writer) = stream.into_split();
+
+    // A single writer task keeps response frames from interleaving; a peer
+    // that does not read fills the channel and so stops the reading below
+    let (responses, mut outgoing) = mpsc::channel::<Vec<u8>>(OUTGOING_FRAMES);
+    let mut writer_task = AbortOnDrop(tokio::spawn(async move {
+        while let Some(frame) = outgoing.recv().await {
+            writer.write_all(&frame).await?;
+        }
+        Ok::<_, io::Error>(())
+    }));
+
+    let ip = context
+        .peer_addr()
+        .expect("accepted connections have a peer")
+        .ip();
+    let mut admission = Admission::new(&ctx.limits, ctx.gate.as_ref(), ip);
+    let in_flight = Arc::new(Semaphore::new(ctx.max_in_flight));
+    let mut frames = FrameDecoder::new(ctx.max_frame_size);
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+    let mut requests = JoinSet::new();
+
+    let result = 'read: loop {
+        loop {
+            let frame = match frames.decode() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    // The stream is out of sync; report it and hang up
+                    error!("Error reading frame from client {}: {}", context, e);
+                    if let Some(response) = frame_error_response(&e) {
+                        let frame = encode_response(0, response, ctx.max_frame_size);
+                        let _ = responses.send(frame).await;
+                    }
+                    break 'read Err(e.into());
+                }
+            };
+
+            let (request_id, request) = match admission.admit(&context, &frame) {
+                Admit::Serve(request_id, request) => (request_id, request),
+                Admit::Answer(request_id, response) => {
+                    let frame = encode_response(request_id, response, ctx.max_frame_size);
+                    let _ = responses.send(frame).await;
+                    continue;
+                }
+                Admit::Disconnect(request_id, response) => {
+                    let frame = encode_response(request_id, response, ctx.max_frame_size);
+                    let _ = responses.send(frame).await;
+                    break 'read Ok(());
+                }
+            };
+
+            // Stops reading while the connection is at its in-flight limit
+            let permit = Arc::clone(&in_flight)
+                .acquire_owned()
+                .await
+                .expect("in-flight semaphore is never closed");
+            let router = Arc::clone(&ctx.router);
+            let context = Arc::clone(&context);
+            let responses = responses.clone();
+            let max_frame_size = ctx.max_frame_size;
+            while requests.try_join_next().is_some() {}
+            requests.spawn(async move {
+                let response = task::spawn_blocking(move || router.dispatch_in(&context, request))
+                    .await
+                    .unwrap_or_else(|e| {
+                        error!("Handler task failed: {}", e);
+                        error_response(ErrorCode::Internal, "handler failed")
+                    });
+                let frame = encode_response(request_id, response, max_frame_size);
+                let _ = responses.send(frame).await;
+                drop(permit);
+            });
+        }
+
+        let read = tokio::select! {
+            read = reader.read(&mut chunk) => read,
+            // Stop reading; what was read already is still answered
+            _ = shutdown.wait_for(|&stop| stop) => break Ok(()),
+        };
+        match read {
+            Ok(0) => {
+                if frames.buffered() > 0 {
+                    warn!("Client {} closed the stream mid-frame", context);
+                }
+                break Ok(());
+            }
+            Ok(n) => frames.extend(&chunk[..n]),
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    // The writer drains once every in-flight task has dropped its sender
+    drop(responses);
+    match (&mut writer_task.0).await {
+        Ok(Ok(())) => result,
+        Ok(Err(e)) => Err(e.into()),
+        Err(e) => Err(Error::Io(io::Error::other(e))),
+    }
+}
@@ -0,0 +1,244 @@
+//! Token authentication for deployments without client certificates.
+//!
+//! A client proves who it is by sending an `AuthRequest` with a token. Until
+//! one succeeds, every other request on the connection is answered with
+//! `ERROR_CODE_UNAUTHENTICATED`. Tokens are checked by an [`Authenticator`];
+//! [`StaticTokens`] reads them from a file.
+
+use super::ConfigError;
+use crate::{
+    handler::{error_response, ConnectionContext},
+    message::{client_message, server_message, AuthResponse, ErrorCode},
+};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Why a token was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The request carried no token
+    Missing,
+    /// The token belongs to nobody
+    Unknown,
+    /// The token was valid once
+    Expired,
+    /// Refused for a reason of the authenticator's own
+    Denied(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "no token given"),
+            AuthError::Unknown => write!(f, "unknown token"),
+            AuthError::Expired => write!(f, "token expired"),
+            AuthError::Denied(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Decides who a token belongs to.
+///
+/// Called from the connection's reader for each `AuthRequest`, before any
+/// later request on that connection is read, so it should answer quickly.
+/// Any matching closure is an authenticator.
+pub trait Authenticator: Send + Sync {
+    /// Returns the principal `token` authenticates, e.g. a device name
+    fn authenticate(&self, token: &str) -> Result<String, AuthError>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&str) -> Result<String, AuthError> + Send + Sync,
+{
+    fn authenticate(&self, token: &str) -> Result<String, AuthError> {
+        self(token)
+    }
+}
+
+/// Fixed set of tokens, each with its principal and an optional expiry.
+///
+/// The file format has one token per line, followed by its principal and
+/// optionally the Unix time in seconds at which it expires:
+///
+/// ```text
+/// # token                           principal   expires
+/// 6f1d0c7e9a2b4e58b3c1d2e3f4a5b6c7  device-42
+/// 0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d  installer   1798761600
+/// ```
+#[derive(Clone, Default)]
+pub struct StaticTokens {
+    tokens: HashMap<String, Grant>,
+}
+
+#[derive(Clone)]
+struct Grant {
+    principal: String,
+    expires: Option<SystemTime>,
+}
+
+impl StaticTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `token` for `principal`, valid until `expires` if given
+    pub fn with_token(
+        mut self,
+        token: impl Into<String>,
+        principal: impl Into<String>,
+        expires: Option<SystemTime>,
+    ) -> Self {
+        let grant = Grant {
+            principal: principal.into(),
+            expires,
+        };
+        self.tokens.insert(token.into(), grant);
+        self
+    }
+
+    /// Reads a token file in the format above
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let bad_file = |reason: String| ConfigError::Value {
+            key: "auth_tokens".to_string(),
+            value: path.display().to_string(),
+            reason,
+        };
+
+        let mut tokens = StaticTokens::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (token, principal, expires) = match fields[..] {
+                [token, principal] => (token, principal, None),
+                [token, principal, expires] => {
+                    let seconds = expires.parse::<u64>().map_err(|e| {
+                        bad_file(format!("line {}: invalid expiry: {}", number + 1, e))
+                    })?;
+                    (
+                        token,
+                        principal,
+                        Some(UNIX_EPOCH + Duration::from_secs(seconds)),
+                    )
+                }
+                _ => {
+                    return Err(bad_file(format!(
+                        "line {}: expected a token, a principal and an optional expiry",
+                        number + 1
+                    )))
+                }
+            };
+            if tokens.tokens.contains_key(token) {
+                return Err(bad_file(format!("line {}: duplicate token", number + 1)));
+            }
+            tokens = tokens.with_token(token, principal, expires);
+        }
+        if tokens.tokens.is_empty() {
+            return Err(bad_file("no tokens found".to_string()));
+        }
+        Ok(tokens)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+impl fmt::Debug for StaticTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the tokens themselves
+        f.debug_struct("StaticTokens")
+            .field("tokens", &self.tokens.len())
+            .finish()
+    }
+}
+
+impl Authenticator for StaticTokens {
+    fn authenticate(&self, token: &str) -> Result<String, AuthError> {
+        if token.is_empty() {
+            return Err(AuthError::Missing);
+        }
+        let grant = self.tokens.get(token).ok_or(AuthError::Unknown)?;
+        if grant
+            .expires
+            .is_some_and(|expires| expires <= SystemTime::now())
+        {
+            return Err(AuthError::Expired);
+        }
+        Ok(grant.principal.clone())
+    }
+}
+
+/// Authentication every connection has to pass before it is served
+#[derive(Clone)]
+pub(crate) struct Gate(Arc<dyn Authenticator>);
+
+impl Gate {
+    pub fn new(authenticator: impl Authenticator + 'static) -> Self {
+        Gate(Arc::new(authenticator))
+    }
+
+    /// Answers `AuthRequest`s, and every other request until one of them
+    /// succeeds; `Ok` hands the request on to the router
+    pub fn check(
+        &self,
+        context: &ConnectionContext,
+        request: client_message::Message,
+    ) -> Result<client_message::Message, server_message::Message> {
+        let client_message::Message::AuthRequest(auth) = request else {
+            return match context.principal() {
+                Some(_) => Ok(request),
+                None => Err(error_response(
+                    ErrorCode::Unauthenticated,
+                    "authenticate before sending requests",
+                )),
+            };
+        };
+
+        if let Some(principal) = context.principal() {
+            return Err(error_response(
+                ErrorCode::Unauthenticated,
+                format!("already authenticated as {}", principal),
+            ));
+        }
+        match self.0.authenticate(&auth.token) {
+            Ok(principal) => {
+                info!("Client {} authenticated as {}", context, principal);
+                context.authenticate(principal.clone());
+                Err(server_message::Message::AuthResponse(AuthResponse {
+                    principal,
+                }))
+            }
+            Err(e) => {
+                warn!("Client {} failed to authenticate: {}", context, e);
+                Err(error_response(ErrorCode::Unauthenticated, e.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gate").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,234 @@
+use super::{auth::Gate, Authenticator, Backend, ConfigError, IpNet, Server, ServerConfig};
+use crate::handler::{AddHandler, Handler, MessageKind, OverflowPolicy, Router};
+use std::{path::PathBuf, time::Duration};
+
+/// Validating way to configure and bind a [`Server`].
+///
+/// ```no_run
+/// use embedded_recruitment_task::server::ServerBuilder;
+/// use std::time::Duration;
+///
+/// let server = ServerBuilder::new("0.0.0.0:8080")
+///     .with_workers(8)
+///     .with_read_timeout(Duration::from_secs(30))
+///     .with_nodelay(true)
+///     .build()?;
+/// # Ok::<(), embedded_recruitment_task::server::ConfigError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    router: Router,
+    gate: Option<Gate>,
+}
+
+impl ServerBuilder {
+    pub fn new(addr: &str) -> Self {
+        Self::from_config(ServerConfig::new(addr))
+    }
+
+    /// Starts from an existing configuration
+    pub fn from_config(config: ServerConfig) -> Self {
+        let router =
+            Router::default().route(MessageKind::Add, AddHandler::new(config.overflow_policy));
+        ServerBuilder {
+            config,
+            router,
+            gate: None,
+        }
+    }
+
+    /// The configuration built so far
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.config.backend = backend;
+        self
+    }
+
+    /// Sets the number of worker threads that run request handlers
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.config.workers = workers;
+        self
+    }
+
+    /// Caps the number of connections served at once.
+    ///
+    /// With [`Backend::Threaded`] each served connection holds one thread of
+    /// a fixed-size pool, and further connections wait in the accept queue.
+    /// [`Backend::EventLoop`] has no queue and refuses anything beyond
+    /// `max_connections` right after `accept`.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// Caps how many connections one IP address may have open; any beyond
+    /// that are closed right after `accept`
+    pub fn with_max_connections_per_ip(mut self, max_connections: usize) -> Self {
+        self.config.max_connections_per_ip = Some(max_connections);
+        self
+    }
+
+    /// Lets in addresses from `net`; once any network is allowed, every
+    /// other address is refused
+    pub fn with_allow(mut self, net: IpNet) -> Self {
+        self.config.allow.push(net);
+        self
+    }
+
+    /// Refuses addresses from `net`, even if they are allowed
+    pub fn with_deny(mut self, net: IpNet) -> Self {
+        self.config.deny.push(net);
+        self
+    }
+
+    /// Sets how many connections may wait for a connection thread before
+    /// further ones are refused
+    pub fn with_accept_queue(mut self, accept_queue: usize) -> Self {
+        self.config.accept_queue = accept_queue;
+        self
+    }
+
+    /// Caps how many requests a single connection may have in flight.
+    ///
+    /// Once a connection reaches the limit the server stops reading from it
+    /// until one of its requests has been answered, so one busy client cannot
+    /// occupy every worker.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.config.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Sets the largest frame payload accepted from or sent to clients
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.config.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Closes connections that send nothing for `timeout`
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Closes connections whose responses cannot be written within `timeout`
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.nodelay = nodelay;
+        self
+    }
+
+    /// Sends TCP keepalive probes after `idle` without traffic
+    pub fn with_keepalive(mut self, idle: Duration) -> Self {
+        self.config.keepalive = Some(idle);
+        self
+    }
+
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.config.backlog = backlog;
+        self
+    }
+
+    /// Logs the content of every request and response
+    pub fn with_log_payloads(mut self, log_payloads: bool) -> Self {
+        self.config.log_payloads = log_payloads;
+        self
+    }
+
+    /// Sets how long `stop` waits for open connections to answer the
+    /// requests they already received before closing them
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Serves TLS with the PEM certificate chain and private key at these
+    /// paths; needs the `tls` feature
+    pub fn with_tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.config.tls_cert = Some(cert.into());
+        self.config.tls_key = Some(key.into());
+        self
+    }
+
+    /// Requires clients to present a certificate signed by one of the CAs
+    /// in this PEM file; handlers see who they are through
+    /// [`ConnectionContext::identity`](crate::handler::ConnectionContext::identity)
+    pub fn with_tls_client_ca(mut self, ca: impl Into<PathBuf>) -> Self {
+        self.config.tls_client_ca = Some(ca.into());
+        self
+    }
+
+    /// Authenticates connections with the tokens in this file; see
+    /// [`StaticTokens`](super::StaticTokens) for the format
+    pub fn with_auth_tokens(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.auth_tokens = Some(path.into());
+        self
+    }
+
+    /// Authenticates connections with `authenticator`, in place of any
+    /// configured token file
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.gate = Some(Gate::new(authenticator));
+        self
+    }
+
+    /// Limits each connection to these requests and payload bytes per
+    /// second; `None` leaves either unlimited
+    pub fn with_rate_limit(mut self, requests: Option<u32>, bytes: Option<u64>) -> Self {
+        self.config.request_rate = requests;
+        self.config.byte_rate = bytes;
+        self
+    }
+
+    /// Limits all connections from one IP address together to these
+    /// requests and payload bytes per second
+    pub fn with_ip_rate_limit(mut self, requests: Option<u32>, bytes: Option<u64>) -> Self {
+        self.config.ip_request_rate = requests;
+        self.config.ip_byte_rate = bytes;
+        self
+    }
+
+    /// Closes connections after this many rate-limited requests in a row
+    pub fn with_rate_limit_strikes(mut self, strikes: u32) -> Self {
+        self.config.rate_limit_strikes = Some(strikes);
+        self
+    }
+
+    /// Replaces the whole handler registry. Add requests, if the router
+    /// serves them, are answered by the built-in add handler with the
+    /// configured overflow policy; register a custom one with `with_handler`
+    /// afterwards
+    pub fn with_router(mut self, router: Router) -> Self {
+        self.router = router;
+        if self.router.handles(MessageKind::Add) {
+            let handler = AddHandler::new(self.config.overflow_policy);
+            self.router.register(MessageKind::Add, handler);
+        }
+        self
+    }
+
+    /// Registers `handler` for `kind`, replacing the built-in one if any
+    pub fn with_handler<H: Handler + 'static>(mut self, kind: MessageKind, handler: H) -> Self {
+        self.router.register(kind, handler);
+        self
+    }
+
+    /// Selects how add requests whose sum overflows an `i32` are answered
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.config.overflow_policy = policy;
+        self.with_handler(MessageKind::Add, AddHandler::new(policy))
+    }
+
+    /// Validates the configuration and binds the listener
+    pub fn build(self) -> Result<Server, ConfigError> {
+        self.config.validate()?;
+        Server::bind(self.config, self.router, self.gate)
+    }
+}
@@ -0,0 +1,41 @@
+use crate::handler::ConnectionContext;
+use log::info;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Connections currently being served, listed in the logs as they come and go
+#[derive(Default)]
+pub(crate) struct ConnectedClients(Mutex<Vec<Arc<ConnectionContext>>>);
+
+impl ConnectedClients {
+    pub fn add(&self, client: &Arc<ConnectionContext>) {
+        let mut clients = self.0.lock().unwrap();
+        clients.push(Arc::clone(client));
+        info!("Connected clients: {}", List(&clients));
+    }
+
+    pub fn remove(&self, client: &Arc<ConnectionContext>) {
+        let mut clients = self.0.lock().unwrap();
+        if let Some(pos) = clients.iter().position(|c| Arc::ptr_eq(c, client)) {
+            clients.remove(pos);
+        }
+        info!("Updated connected clients: {}", List(&clients));
+    }
+}
+
+struct List<'a>(&'a [Arc<ConnectionContext>]);
+
+impl fmt::Display for List<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, client) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", client)?;
+        }
+        write!(f, "]")
+    }
+}
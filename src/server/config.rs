@@ -0,0 +1,675 @@
+use super::{
+    Backend, DEFAULT_ACCEPT_QUEUE, DEFAULT_MAX_CONNECTIONS, DEFAULT_MAX_IN_FLIGHT,
+    DEFAULT_SHUTDOWN_TIMEOUT,
+};
+use crate::{framing::DEFAULT_MAX_FRAME_SIZE, handler::OverflowPolicy};
+use ipnet::IpNet;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::{
+    env,
+    error::Error,
+    fmt, fs, io,
+    net::{IpAddr, TcpListener, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// Address listened on when the configuration does not name one
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Connections the kernel queues before `accept` by default
+pub const DEFAULT_BACKLOG: u32 = 128;
+
+/// Prefix of the environment variables that override configuration keys,
+/// e.g. `ERT_SERVER_ADDR` for `addr`
+pub const ENV_PREFIX: &str = "ERT_SERVER_";
+
+/// Everything that shapes how a [`Server`](super::Server) listens and serves.
+///
+/// Build one with [`ServerBuilder`](super::ServerBuilder), load it from a
+/// TOML file, or fill it in directly and pass it to
+/// [`ServerBuilder::from_config`](super::ServerBuilder::from_config).
+///
+/// The file uses the field names as keys. Durations are written with a unit
+/// (`"250ms"`, `"30s"`, `"5m"`) or as whole seconds, and optional ones are
+/// turned off with `"off"`:
+///
+/// ```toml
+/// addr = "0.0.0.0:8080"
+/// backend = "event_loop"
+/// workers = 8
+/// keepalive = "60s"
+/// read_timeout = "off"
+/// ```
+///
+/// The [`Display`](fmt::Display) output is in the same format and lists
+/// every key, which makes it handy for logging the effective configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Address to listen on, e.g. `0.0.0.0:8080`
+    pub addr: String,
+    pub backend: Backend,
+    /// Threads running request handlers
+    pub workers: usize,
+    /// Connections served at the same time
+    pub max_connections: usize,
+    /// Connections one IP address may have open at the same time
+    pub max_connections_per_ip: Option<usize>,
+    /// Accepted connections that may wait for a connection thread
+    pub accept_queue: usize,
+    /// Requests a single connection may have queued or in progress
+    pub max_in_flight: usize,
+    /// Largest frame payload accepted from or sent to clients
+    pub max_frame_size: usize,
+    /// How long a connection may stay silent before it is closed
+    pub read_timeout: Option<Duration>,
+    /// How long writing a response may block before the connection is closed
+    pub write_timeout: Option<Duration>,
+    /// Disables Nagle's algorithm on accepted connections
+    pub nodelay: bool,
+    /// Idle time before TCP keepalive probes are sent; `None` disables them
+    pub keepalive: Option<Duration>,
+    /// Length of the kernel's queue of connections waiting for `accept`
+    pub backlog: u32,
+    /// Logs every request and response, which may expose sensitive data
+    pub log_payloads: bool,
+    /// How the built-in add handler answers sums that overflow an `i32`
+    pub overflow_policy: OverflowPolicy,
+    /// How long `stop` lets open connections finish their requests
+    pub shutdown_timeout: Duration,
+    /// PEM certificate chain served to clients; TLS is on when this and
+    /// `tls_key` are set, which needs the `tls` feature
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key matching `tls_cert`
+    pub tls_key: Option<PathBuf>,
+    /// PEM certificates of the CAs trusted to sign client certificates;
+    /// when set, clients without a valid certificate are refused
+    pub tls_client_ca: Option<PathBuf>,
+    /// Token file for [`StaticTokens`](super::StaticTokens); when set,
+    /// connections must authenticate before anything else is served
+    pub auth_tokens: Option<PathBuf>,
+    /// Requests per second one connection may send; the excess is answered
+    /// with `ERROR_CODE_RATE_LIMITED`
+    pub request_rate: Option<u32>,
+    /// Request payload bytes per second one connection may send
+    pub byte_rate: Option<u64>,
+    /// Requests per second shared by all connections from one IP address
+    pub ip_request_rate: Option<u32>,
+    /// Request payload bytes per second shared by all connections from one
+    /// IP address
+    pub ip_byte_rate: Option<u64>,
+    /// Rate-limited requests in a row after which the connection is closed
+    pub rate_limit_strikes: Option<u32>,
+    /// Networks allowed to connect; when not empty, every other address is
+    /// closed right after `accept`
+    pub allow: Vec<IpNet>,
+    /// Networks closed right after `accept`, even if they are allowed
+    pub deny: Vec<IpNet>,
+}
+
+impl ServerConfig {
+    pub fn new(addr: &str) -> Self {
+        ServerConfig {
+            addr: addr.to_string(),
+            backend: Backend::default(),
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_connections_per_ip: None,
+            accept_queue: DEFAULT_ACCEPT_QUEUE,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            read_timeout: None,
+            write_timeout: None,
+            nodelay: false,
+            keepalive: None,
+            backlog: DEFAULT_BACKLOG,
+            log_payloads: false,
+            overflow_policy: OverflowPolicy::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            auth_tokens: None,
+            request_rate: None,
+            byte_rate: None,
+            ip_request_rate: None,
+            ip_byte_rate: None,
+            rate_limit_strikes: None,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+
+    /// Reads the configuration from a TOML file; missing keys keep their
+    /// defaults
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&text)
+    }
+
+    /// Parses the configuration from TOML text
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError::Syntax(e.to_string()))?;
+
+        let mut config = ServerConfig::default();
+        for (key, value) in &table {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                // Lists of networks; the environment spells them comma-separated
+                toml::Value::Array(items) if items.iter().all(toml::Value::is_str) => items
+                    .iter()
+                    .filter_map(toml::Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => {
+                    return Err(ConfigError::Value {
+                        key: key.clone(),
+                        value: other.to_string(),
+                        reason: "expected a string, integer, boolean or list of strings"
+                            .to_string(),
+                    })
+                }
+            };
+            config.set_from(key, &value, key)?;
+        }
+        Ok(config)
+    }
+
+    /// Overrides keys from `ERT_SERVER_*` environment variables
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_vars(env::vars())
+    }
+
+    /// Overrides keys from `(name, value)` pairs named like environment
+    /// variables; names without the [`ENV_PREFIX`] are ignored
+    pub fn apply_vars<I, K, V>(&mut self, vars: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in vars {
+            let name = name.as_ref();
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                self.set_from(&key.to_ascii_lowercase(), value.as_ref(), name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a single key from its text form, as it would appear in the file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        self.set_from(key, value, key)
+    }
+
+    /// Like `set`, but errors name `source` (e.g. the environment variable)
+    fn set_from(&mut self, key: &str, value: &str, source: &str) -> Result<(), ConfigError> {
+        match key {
+            "addr" => self.addr = value.to_string(),
+            "backend" => {
+                self.backend = match value {
+                    "threaded" => Backend::Threaded,
+                    "event_loop" => Backend::EventLoop,
+                    _ => return Err(bad_value(source, value, "expected threaded or event_loop")),
+                }
+            }
+            "workers" => self.workers = parse(source, value)?,
+            "max_connections" => self.max_connections = parse(source, value)?,
+            "max_connections_per_ip" => {
+                self.max_connections_per_ip = parse_optional(source, value)?
+            }
+            "accept_queue" => self.accept_queue = parse(source, value)?,
+            "max_in_flight" => self.max_in_flight = parse(source, value)?,
+            "max_frame_size" => self.max_frame_size = parse(source, value)?,
+            "read_timeout" => self.read_timeout = parse_optional_duration(source, value)?,
+            "write_timeout" => self.write_timeout = parse_optional_duration(source, value)?,
+            "nodelay" => self.nodelay = parse(source, value)?,
+            "keepalive" => self.keepalive = parse_optional_duration(source, value)?,
+            "backlog" => self.backlog = parse(source, value)?,
+            "log_payloads" => self.log_payloads = parse(source, value)?,
+            "overflow_policy" => {
+                self.overflow_policy = match value {
+                    "error" => OverflowPolicy::Error,
+                    "saturate" => OverflowPolicy::Saturate,
+                    "widen" => OverflowPolicy::Widen,
+                    _ => {
+                        return Err(bad_value(
+                            source,
+                            value,
+                            "expected error, saturate or widen",
+                        ))
+                    }
+                }
+            }
+            "shutdown_timeout" => self.shutdown_timeout = parse_duration(source, value)?,
+            "tls_cert" => self.tls_cert = parse_optional_path(value),
+            "tls_key" => self.tls_key = parse_optional_path(value),
+            "tls_client_ca" => self.tls_client_ca = parse_optional_path(value),
+            "auth_tokens" => self.auth_tokens = parse_optional_path(value),
+            "request_rate" => self.request_rate = parse_optional(source, value)?,
+            "byte_rate" => self.byte_rate = parse_optional(source, value)?,
+            "ip_request_rate" => self.ip_request_rate = parse_optional(source, value)?,
+            "ip_byte_rate" => self.ip_byte_rate = parse_optional(source, value)?,
+            "rate_limit_strikes" => self.rate_limit_strikes = parse_optional(source, value)?,
+            "allow" => self.allow = parse_networks(source, value)?,
+            "deny" => self.deny = parse_networks(source, value)?,
+            _ => {
+                return Err(ConfigError::UnknownKey {
+                    key: source.to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every setting and the combinations between them
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key, reason| Err(ConfigError::Invalid { key, reason });
+
+        if self.addr.is_empty() {
+            return invalid("addr", "must not be empty");
+        }
+        if self.workers == 0 {
+            return invalid("workers", "must be at least 1");
+        }
+        if self.max_connections == 0 {
+            return invalid("max_connections", "must be at least 1");
+        }
+        if self.max_connections_per_ip == Some(0) {
+            return invalid("max_connections_per_ip", "must be at least 1, or off");
+        }
+        if self.max_in_flight == 0 {
+            return invalid("max_in_flight", "must be at least 1");
+        }
+        if self.max_frame_size == 0 {
+            return invalid("max_frame_size", "must be at least 1");
+        }
+        // The socket API takes zero to mean "no timeout"; `None` says that
+        if self.read_timeout == Some(Duration::ZERO) {
+            return invalid("read_timeout", "must be greater than zero");
+        }
+        if self.write_timeout == Some(Duration::ZERO) {
+            return invalid("write_timeout", "must be greater than zero");
+        }
+        if self
+            .keepalive
+            .is_some_and(|idle| idle < Duration::from_secs(1))
+        {
+            return invalid("keepalive", "must be at least one second");
+        }
+        if self.backlog == 0 || self.backlog > i32::MAX as u32 {
+            return invalid("backlog", "must be between 1 and 2147483647");
+        }
+        // Zero would refuse everything; "off" is how to turn a limit off
+        let rates = [
+            ("request_rate", self.request_rate.map(u64::from)),
+            ("byte_rate", self.byte_rate),
+            ("ip_request_rate", self.ip_request_rate.map(u64::from)),
+            ("ip_byte_rate", self.ip_byte_rate),
+            ("rate_limit_strikes", self.rate_limit_strikes.map(u64::from)),
+        ];
+        for (key, rate) in rates {
+            if rate == Some(0) {
+                return invalid(key, "must be at least 1, or off");
+            }
+        }
+
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => {
+                return Err(ConfigError::Conflict {
+                    key: "tls_cert",
+                    other: "tls_key",
+                    reason: "a certificate needs its private key",
+                })
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::Conflict {
+                    key: "tls_key",
+                    other: "tls_cert",
+                    reason: "a private key needs its certificate",
+                })
+            }
+            (Some(_), Some(_)) if cfg!(not(feature = "tls")) => {
+                return invalid("tls_cert", "TLS needs the tls feature");
+            }
+            _ => {}
+        }
+        if self.tls_client_ca.is_some() && !self.tls_enabled() {
+            return Err(ConfigError::Conflict {
+                key: "tls_client_ca",
+                other: "tls_cert",
+                reason: "client certificates need TLS",
+            });
+        }
+
+        if self.backend == Backend::EventLoop {
+            // Event loop sockets never block, so there is nothing to time out
+            let conflict = |key| {
+                Err(ConfigError::Conflict {
+                    key,
+                    other: "backend",
+                    reason: "socket timeouts require the threaded backend",
+                })
+            };
+            if self.read_timeout.is_some() {
+                return conflict("read_timeout");
+            }
+            if self.write_timeout.is_some() {
+                return conflict("write_timeout");
+            }
+            if self.tls_enabled() {
+                return Err(ConfigError::Conflict {
+                    key: "tls_cert",
+                    other: "backend",
+                    reason: "TLS requires the threaded backend",
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// True if accepted connections are wrapped in TLS
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert.is_some() && self.tls_key.is_some()
+    }
+
+    /// Binds the listening socket with the configured backlog
+    pub(crate) fn bind(&self) -> io::Result<TcpListener> {
+        let mut last_error = None;
+        for addr in self.addr.to_socket_addrs()? {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            // Matches what `TcpListener::bind` does on Unix
+            #[cfg(unix)]
+            socket.set_reuse_address(true)?;
+            match socket
+                .bind(&addr.into())
+                .and_then(|_| socket.listen(self.backlog as i32))
+            {
+                Ok(()) => return Ok(socket.into()),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        }))
+    }
+
+    /// Applies the per-connection socket options to an accepted connection
+    pub(crate) fn configure(&self, socket: SockRef<'_>) -> io::Result<()> {
+        socket.set_tcp_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        socket.set_read_timeout(self.read_timeout)?;
+        socket.set_write_timeout(self.write_timeout)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig::new(DEFAULT_ADDR)
+    }
+}
+
+impl fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "addr = {:?}", self.addr)?;
+        writeln!(f, "backend = \"{}\"", self.backend)?;
+        writeln!(f, "workers = {}", self.workers)?;
+        writeln!(f, "max_connections = {}", self.max_connections)?;
+        writeln!(
+            f,
+            "max_connections_per_ip = {}",
+            OptionalNumber(self.max_connections_per_ip)
+        )?;
+        writeln!(f, "accept_queue = {}", self.accept_queue)?;
+        writeln!(f, "max_in_flight = {}", self.max_in_flight)?;
+        writeln!(f, "max_frame_size = {}", self.max_frame_size)?;
+        writeln!(
+            f,
+            "read_timeout = \"{}\"",
+            OptionalDuration(self.read_timeout)
+        )?;
+        writeln!(
+            f,
+            "write_timeout = \"{}\"",
+            OptionalDuration(self.write_timeout)
+        )?;
+        writeln!(f, "nodelay = {}", self.nodelay)?;
+        writeln!(f, "keepalive = \"{}\"", OptionalDuration(self.keepalive))?;
+        writeln!(f, "backlog = {}", self.backlog)?;
+        writeln!(f, "log_payloads = {}", self.log_payloads)?;
+        writeln!(f, "overflow_policy = \"{}\"", self.overflow_policy)?;
+        writeln!(
+            f,
+            "shutdown_timeout = \"{}\"",
+            OptionalDuration(Some(self.shutdown_timeout))
+        )?;
+        writeln!(f, "tls_cert = {}", OptionalPath(&self.tls_cert))?;
+        writeln!(f, "tls_key = {}", OptionalPath(&self.tls_key))?;
+        writeln!(f, "tls_client_ca = {}", OptionalPath(&self.tls_client_ca))?;
+        writeln!(f, "auth_tokens = {}", OptionalPath(&self.auth_tokens))?;
+        writeln!(f, "request_rate = {}", OptionalNumber(self.request_rate))?;
+        writeln!(f, "byte_rate = {}", OptionalNumber(self.byte_rate))?;
+        writeln!(
+            f,
+            "ip_request_rate = {}",
+            OptionalNumber(self.ip_request_rate)
+        )?;
+        writeln!(f, "ip_byte_rate = {}", OptionalNumber(self.ip_byte_rate))?;
+        writeln!(
+            f,
+            "rate_limit_strikes = {}",
+            OptionalNumber(self.rate_limit_strikes)
+        )?;
+        writeln!(f, "allow = {}", Networks(&self.allow))?;
+        writeln!(f, "deny = {}", Networks(&self.deny))
+    }
+}
+
+/// Writes a duration the way the configuration file spells it
+struct OptionalDuration(Option<Duration>);
+
+impl fmt::Display for OptionalDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "off"),
+            Some(d) if d.subsec_millis() == 0 => write!(f, "{}s", d.as_secs()),
+            Some(d) => write!(f, "{}ms", d.as_millis()),
+        }
+    }
+}
+
+/// Writes a number, or `"off"` if there is none
+struct OptionalNumber<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for OptionalNumber<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "\"off\""),
+            Some(ref n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Writes networks as a TOML list of strings
+struct Networks<'a>(&'a [IpNet]);
+
+impl fmt::Display for Networks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, net) in self.0.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(f, "{}\"{}\"", separator, net)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Writes a path as a TOML string, or `"off"` if there is none
+struct OptionalPath<'a>(&'a Option<PathBuf>);
+
+impl fmt::Display for OptionalPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "\"off\""),
+            Some(path) => write!(f, "{:?}", path.display().to_string()),
+        }
+    }
+}
+
+fn bad_value(key: &str, value: &str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Value {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: reason.into(),
+    }
+}
+
+fn parse<T>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e: T::Err| bad_value(key, value, e.to_string()))
+}
+
+/// Parses `250ms`, `30s`, `5m`, `1h` or a plain number of seconds
+fn parse_duration(key: &str, value: &str) -> Result<Duration, ConfigError> {
+    let text = value.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| bad_value(key, value, "expected a duration such as 500ms or 30s"))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount.saturating_mul(60))),
+        "h" => Ok(Duration::from_secs(amount.saturating_mul(3600))),
+        _ => Err(bad_value(key, value, "unit must be one of ms, s, m or h")),
+    }
+}
+
+fn parse_optional_duration(key: &str, value: &str) -> Result<Option<Duration>, ConfigError> {
+    match value.trim() {
+        "off" => Ok(None),
+        _ => parse_duration(key, value).map(Some),
+    }
+}
+
+fn parse_optional<T>(key: &str, value: &str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match value.trim() {
+        "off" => Ok(None),
+        _ => parse(key, value).map(Some),
+    }
+}
+
+/// Parses comma-separated networks such as `10.0.0.0/8, ::1`; a bare
+/// address is a network of one
+fn parse_networks(key: &str, value: &str) -> Result<Vec<IpNet>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|net| !net.is_empty())
+        .map(|net| {
+            net.parse::<IpNet>()
+                .or_else(|_| net.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| bad_value(key, value, format!("{} is not a network or address", net)))
+        })
+        .collect()
+}
+
+fn parse_optional_path(value: &str) -> Option<PathBuf> {
+    match value.trim() {
+        "off" | "" => None,
+        path => Some(PathBuf::from(path)),
+    }
+}
+
+/// Why a [`ServerConfig`] was rejected or could not be put to use.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A setting has a value it can never take
+    Invalid {
+        key: &'static str,
+        reason: &'static str,
+    },
+    /// A setting cannot be used together with the value of `other`
+    Conflict {
+        key: &'static str,
+        other: &'static str,
+        reason: &'static str,
+    },
+    /// The file or environment names a key that does not exist
+    UnknownKey { key: String },
+    /// A key's value cannot be parsed into the type it needs
+    Value {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    /// The configuration file is not valid TOML
+    Syntax(String),
+    /// The configuration file could not be read
+    Read { path: PathBuf, source: io::Error },
+    /// Binding the listener failed
+    Io(io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Invalid { key, reason } => write!(f, "invalid {}: {}", key, reason),
+            ConfigError::Conflict { key, other, reason } => {
+                write!(f, "{} conflicts with {}: {}", key, other, reason)
+            }
+            ConfigError::UnknownKey { key } => write!(f, "unknown configuration key {}", key),
+            ConfigError::Value { key, value, reason } => {
+                write!(f, "invalid value {:?} for {}: {}", value, key, reason)
+            }
+            ConfigError::Syntax(e) => write!(f, "malformed configuration file: {}", e),
+            ConfigError::Read { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            ConfigError::Io(e) => write!(f, "failed to bind listener: {}", e),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(e) | ConfigError::Read { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
@@ -0,0 +1,449 @@
+//! Readiness-based backend: one thread multiplexes the listener and every
+//! connection through epoll (via mio), while request handlers run on the
+//! shared worker pool. Finished responses come back over a channel and the
+//! loop is woken with a [`Waker`], so nothing ever sleeps to poll.
+
+use super::{
+    access::Slot, dispatch, encode_response, frame_error_response, pool::ThreadPool, screen,
+    Admission, Admit, Server, ShutdownReport,
+};
+use crate::{
+    framing::FrameDecoder,
+    handler::{ConnectionContext, Router},
+};
+use log::{debug, error, info, warn};
+use mio::{
+    net::{TcpListener, TcpStream},
+    Events, Interest, Poll, Registry, Token, Waker,
+};
+use socket2::SockRef;
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::Instant,
+};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+
+/// Size of the chunk used when pulling bytes from a socket
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// How many maximum-size frames a connection may have waiting to be written
+/// before it stops reading requests
+const OUTGOING_FRAMES: usize = 4;
+
+/// A response encoded by a worker, on its way back to the event loop
+struct Completion {
+    token: Token,
+    frame: Vec<u8>,
+}
+
+/// Everything the connections need from the loop to hand off requests
+struct Context {
+    router: Arc<Router>,
+    workers: Arc<ThreadPool>,
+    completions: Sender<Completion>,
+    waker: Arc<Waker>,
+    max_frame_size: usize,
+    max_in_flight: usize,
+    max_outgoing: usize,
+    log_payloads: bool,
+}
+
+struct Connection {
+    stream: TcpStream,
+    addr: SocketAddr,
+    context: Arc<ConnectionContext>,
+    admission: Admission,
+    _slot: Slot, // Counts against the per-address cap while open
+    frames: FrameDecoder,
+    outgoing: Vec<u8>, // Encoded frames not yet written to the socket
+    in_flight: usize,
+    read_closed: bool, // Peer hung up or sent something unrecoverable
+    failed: bool,      // The socket is broken; drop without flushing
+}
+
+impl Connection {
+    /// Reads and dispatches requests until the socket would block, the
+    /// connection reaches its in-flight limit or the peer stops reading
+    fn pump(&mut self, token: Token, ctx: &Context) {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            self.dispatch_frames(token, ctx);
+            if self.read_closed || self.is_busy(ctx) {
+                // Reading resumes from `complete` once a slot frees up, or
+                // on the next writable event once responses got out
+                return;
+            }
+
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    if self.frames.buffered() > 0 {
+                        warn!("Client {} closed the stream mid-frame", self.addr);
+                    }
+                    self.read_closed = true;
+                }
+                Ok(n) => self.frames.extend(&chunk[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("Error reading from client {}: {}", self.addr, e);
+                    self.read_closed = true;
+                    self.failed = true;
+                }
+            }
+        }
+    }
+
+    /// Hands complete frames to the workers, up to the in-flight limit
+    fn dispatch_frames(&mut self, token: Token, ctx: &Context) {
+        while !self.read_closed && !self.is_busy(ctx) {
+            let frame = match self.frames.decode() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return,
+                Err(e) => {
+                    // The stream is out of sync; report it and hang up
+                    error!("Error reading frame from client {}: {}", self.addr, e);
+                    if let Some(response) = frame_error_response(&e) {
+                        self.queue(encode_response(0, response, ctx.max_frame_size));
+                    }
+                    self.read_closed = true;
+                    return;
+                }
+            };
+
+            let (request_id, request) = match self.admission.admit(&self.context, &frame) {
+                Admit::Serve(request_id, request) => (request_id, request),
+                Admit::Answer(request_id, response) => {
+                    self.queue(encode_response(request_id, response, ctx.max_frame_size));
+                    continue;
+                }
+                Admit::Disconnect(request_id, response) => {
+                    self.queue(encode_response(request_id, response, ctx.max_frame_size));
+                    self.read_closed = true;
+                    return;
+                }
+            };
+
+            self.in_flight += 1;
+            let context = Arc::clone(&self.context);
+            let router = Arc::clone(&ctx.router);
+            let completions = ctx.completions.clone();
+            let waker = Arc::clone(&ctx.waker);
+            let max_frame_size = ctx.max_frame_size;
+            let log_payloads = ctx.log_payloads;
+            ctx.workers.execute(move || {
+                let response = dispatch(&router, &context, request_id, request, log_payloads);
+                let frame = encode_response(request_id, response, max_frame_size);
+                // The loop may be gone already if the server stopped
+                if completions.send(Completion { token, frame }).is_ok() {
+                    if let Err(e) = waker.wake() {
+                        error!("Failed to wake the event loop: {}", e);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Takes back a response from the workers and resumes reading
+    fn complete(&mut self, token: Token, frame: Vec<u8>, ctx: &Context) {
+        self.in_flight -= 1;
+        self.queue(frame);
+        self.pump(token, ctx);
+    }
+
+    fn queue(&mut self, frame: Vec<u8>) {
+        self.outgoing.extend_from_slice(&frame);
+        self.flush();
+    }
+
+    /// Writes as much of the outgoing buffer as the socket takes
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() && !self.failed {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.failed = true,
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                // Finished when the socket reports writable again
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("Failed to send response to {}: {}", self.addr, e);
+                    self.failed = true;
+                }
+            }
+        }
+    }
+
+    /// True if no more requests should be taken until responses are
+    /// answered or written; answers from admission count against the
+    /// outgoing buffer alone
+    fn is_busy(&self, ctx: &Context) -> bool {
+        self.in_flight >= ctx.max_in_flight || self.outgoing.len() >= ctx.max_outgoing
+    }
+
+    /// True once there is nothing left to read, answer or write
+    fn is_done(&self) -> bool {
+        self.failed || (self.read_closed && self.in_flight == 0 && self.outgoing.is_empty())
+    }
+}
+
+struct EventLoop<'a> {
+    server: &'a Server,
+    poll: Poll,
+    listener: TcpListener,
+    connections: HashMap<Token, Connection>,
+    next_token: usize,
+    ctx: Context,
+}
+
+/// Runs [`Backend::EventLoop`](super::Backend::EventLoop) until the server is
+/// stopped, then drains the open connections
+pub(super) fn run(server: &Server, workers: Arc<ThreadPool>) -> io::Result<ShutdownReport> {
+    let poll = Poll::new()?;
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+
+    // The std listener is already non-blocking, as mio requires
+    let mut listener = TcpListener::from_std(server.listener.try_clone()?);
+    poll.registry()
+        .register(&mut listener, LISTENER, Interest::READABLE)?;
+
+    let (sender, completions) = mpsc::channel();
+    let mut event_loop = EventLoop {
+        server,
+        poll,
+        listener,
+        connections: HashMap::new(),
+        next_token: FIRST_CONNECTION,
+        ctx: Context {
+            router: Arc::clone(&server.router),
+            workers,
+            completions: sender,
+            waker: Arc::clone(&waker),
+            max_frame_size: server.config.max_frame_size,
+            max_in_flight: server.config.max_in_flight,
+            max_outgoing: server.config.max_frame_size.saturating_mul(OUTGOING_FRAMES),
+            log_payloads: server.config.log_payloads,
+        },
+    };
+
+    // Let `stop` wake the loop instead of connecting to the listener
+    *server.waker.lock().unwrap() = Some(waker);
+    let result = event_loop
+        .run(&completions)
+        .and_then(|_| event_loop.drain(&completions));
+    *server.waker.lock().unwrap() = None;
+
+    event_loop.close_all();
+    result
+}
+
+impl EventLoop<'_> {
+    fn run(&mut self, completions: &Receiver<Completion>) -> io::Result<()> {
+        let mut events = Events::with_capacity(1024);
+
+        while self.server.is_running.load(Ordering::SeqCst) {
+            if let Err(e) = self.poll.poll(&mut events, None) {
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            self.process(&events, completions)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stops accepting and reading, then keeps answering the requests already
+    /// read until they are done or the shutdown timeout runs out
+    fn drain(&mut self, completions: &Receiver<Completion>) -> io::Result<ShutdownReport> {
+        let deadline = Instant::now() + self.server.config.shutdown_timeout;
+        self.poll.registry().deregister(&mut self.listener)?;
+
+        let open = self.connections.len();
+        debug!("Draining {} connections", open);
+        let tokens: Vec<_> = self.connections.keys().copied().collect();
+        for token in tokens {
+            if let Some(connection) = self.connections.get_mut(&token) {
+                connection.read_closed = true;
+            }
+            self.close_if_done(token);
+        }
+
+        let mut events = Events::with_capacity(1024);
+        while !self.connections.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Err(e) = self.poll.poll(&mut events, Some(remaining)) {
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+            self.process(&events, completions)?;
+        }
+
+        // `close_all` takes care of whatever is left
+        let forced = self.connections.len();
+        if forced > 0 {
+            warn!("Closing {} connections still busy at the deadline", forced);
+        }
+        Ok(ShutdownReport {
+            drained: open - forced,
+            forced,
+        })
+    }
+
+    fn process(&mut self, events: &Events, completions: &Receiver<Completion>) -> io::Result<()> {
+        for event in events.iter() {
+            match event.token() {
+                LISTENER => self.accept(),
+                // Completions are drained below after every wake-up
+                WAKER => {}
+                token => {
+                    if let Some(connection) = self.connections.get_mut(&token) {
+                        if event.is_writable() {
+                            connection.flush();
+                        }
+                        // Reading may have stopped on a full outgoing buffer
+                        // rather than an empty socket
+                        if event.is_readable() || event.is_read_closed() || event.is_writable() {
+                            connection.pump(token, &self.ctx);
+                        }
+                    }
+                    self.close_if_done(token);
+                }
+            }
+        }
+
+        while let Ok(Completion { token, frame }) = completions.try_recv() {
+            // Responses for connections that are already gone are dropped
+            if let Some(connection) = self.connections.get_mut(&token) {
+                connection.complete(token, frame, &self.ctx);
+            }
+            self.close_if_done(token);
+        }
+
+        Ok(())
+    }
+
+    /// Accepts until the listener would block; events are edge-triggered
+    fn accept(&mut self) {
+        loop {
+            let (mut stream, addr) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("Error accepting connection: {}", e);
+                    return;
+                }
+            };
+            info!("New client connected: {}", addr);
+            let Some(slot) = screen(&self.server.access, &self.server.metrics, addr) else {
+                continue;
+            };
+
+            let metrics = &self.server.metrics;
+            metrics.connection_queued();
+            if self.connections.len() >= self.server.config.max_connections {
+                // Dropping the stream closes it
+                metrics.connection_refused();
+                warn!(
+                    "Refused client {}: already serving {} connections",
+                    addr, self.server.config.max_connections
+                );
+                continue;
+            }
+
+            if let Err(e) = self.server.config.configure(SockRef::from(&stream)) {
+                // The connection still works, just without the tuning
+                warn!("Failed to set socket options for {}: {}", addr, e);
+            }
+
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            let registered = self.poll.registry().register(
+                &mut stream,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            );
+            if let Err(e) = registered {
+                // Only this connection is lost; dropping it frees its slot
+                metrics.connection_refused();
+                error!("Failed to register client {}: {}", addr, e);
+                continue;
+            }
+            metrics.connection_started();
+
+            let context = Arc::new(ConnectionContext::new(addr));
+            self.server.clients.add(&context);
+
+            self.connections.insert(
+                token,
+                Connection {
+                    stream,
+                    addr,
+                    admission: Admission::new(
+                        &self.server.limits,
+                        self.server.gate.as_ref(),
+                        addr.ip(),
+                    ),
+                    context,
+                    _slot: slot,
+                    frames: FrameDecoder::new(self.server.config.max_frame_size),
+                    outgoing: Vec::new(),
+                    in_flight: 0,
+                    read_closed: false,
+                    failed: false,
+                },
+            );
+        }
+    }
+
+    fn close_if_done(&mut self, token: Token) {
+        if self
+            .connections
+            .get(&token)
+            .is_some_and(Connection::is_done)
+        {
+            if let Some(connection) = self.connections.remove(&token) {
+                self.close(connection);
+            }
+        }
+    }
+
+    fn close_all(&mut self) {
+        let connections: Vec<_> = self.connections.drain().map(|(_, c)| c).collect();
+        debug!("Closing {} connections", connections.len());
+        for connection in connections {
+            self.close(connection);
+        }
+    }
+
+    fn close(&self, mut connection: Connection) {
+        deregister(self.poll.registry(), &mut connection.stream);
+        info!("Client {} disconnected.", connection.addr);
+        self.server.clients.remove(&connection.context);
+        self.server.metrics.connection_finished();
+    }
+}
+
+fn deregister(registry: &Registry, stream: &mut TcpStream) {
+    if let Err(e) = registry.deregister(stream) {
+        debug!("Failed to deregister connection: {}", e);
+    }
+}
@@ -0,0 +1,85 @@
+use super::{MetricsSnapshot, Server, ShutdownReport};
+use crate::error::{Error, Result};
+use log::error;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+/// A [`Server`] running on its own thread, as returned by [`Server::spawn`].
+///
+/// Dropping the handle stops the server and waits for it to drain, so a
+/// server never outlives the scope that started it.
+pub struct ServerHandle {
+    server: Arc<Server>,
+    local_addr: SocketAddr,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl ServerHandle {
+    pub(super) fn spawn(server: Server) -> Result<Self> {
+        let local_addr = server.listener.local_addr()?;
+        let server = Arc::new(server);
+        let thread = {
+            let server = Arc::clone(&server);
+            thread::Builder::new()
+                .name("server".to_string())
+                .spawn(move || server.run())?
+        };
+        Ok(ServerHandle {
+            server,
+            local_addr,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server accepts connections on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.local_addr.port()
+    }
+
+    /// Current connection counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.server.metrics()
+    }
+
+    /// Stops the server; see [`Server::stop`]
+    pub fn stop(&self) -> ShutdownReport {
+        self.server.stop()
+    }
+
+    /// Waits for the server thread to exit and returns how the shutdown went.
+    ///
+    /// This does not stop the server by itself: it returns once some other
+    /// thread has called [`ServerHandle::stop`], or when `run` fails.
+    pub fn join(mut self) -> Result<ShutdownReport> {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> Result<ShutdownReport> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(ShutdownReport::default());
+        };
+        match thread.join() {
+            Ok(result) => result.map(|_| self.server.lifecycle.wait()),
+            Err(_) => Err(Error::Io(io::Error::other("server thread panicked"))),
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.server.stop();
+            if let Err(e) = self.wait() {
+                error!("Server failed: {}", e);
+            }
+        }
+    }
+}
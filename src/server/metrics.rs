@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Live counters describing what the server is doing.
+///
+/// All counters are updated with relaxed atomics; a [`MetricsSnapshot`] is a
+/// consistent-enough view for logging and monitoring, not an exact audit.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connections_accepted: AtomicU64,
+    connections_refused: AtomicU64,
+    connections_denied: AtomicU64,
+    connections_queued: AtomicU64,
+    connections_active: AtomicU64,
+}
+
+/// Point-in-time copy of the server [`Metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Connections handed to the connection pool since start-up
+    pub connections_accepted: u64,
+    /// Connections closed right away because a connection limit was reached
+    /// or the connection could not be set up
+    pub connections_refused: u64,
+    /// Connections closed right away by the allow and deny lists
+    pub connections_denied: u64,
+    /// Accepted connections waiting for a free connection thread
+    pub connections_queued: u64,
+    /// Connections currently being served
+    pub connections_active: u64,
+}
+
+impl Metrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            connections_accepted: self.connections_accepted.load(Ordering::Relaxed),
+            connections_refused: self.connections_refused.load(Ordering::Relaxed),
+            connections_denied: self.connections_denied.load(Ordering::Relaxed),
+            connections_queued: self.connections_queued.load(Ordering::Relaxed),
+            connections_active: self.connections_active.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn connection_queued(&self) {
+        self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+        self.connections_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Undoes [`Metrics::connection_queued`] for a connection the pool rejected
+    pub(crate) fn connection_refused(&self) {
+        self.connections_accepted.fetch_sub(1, Ordering::Relaxed);
+        self.connections_queued.fetch_sub(1, Ordering::Relaxed);
+        self.connections_refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection closed before it was queued, by the allow and
+    /// deny lists if `denied`, else by a connection limit
+    pub(crate) fn connection_rejected(&self, denied: bool) {
+        let counter = match denied {
+            true => &self.connections_denied,
+            false => &self.connections_refused,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_started(&self) {
+        self.connections_queued.fetch_sub(1, Ordering::Relaxed);
+        self.connections_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_finished(&self) {
+        self.connections_active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
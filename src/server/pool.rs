@@ -0,0 +1,127 @@
+use log::{debug, error};
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed set of worker threads pulling jobs from a shared queue.
+///
+/// Dropping the pool closes the queue; the workers finish what is already
+/// queued and then exit on their own.
+pub(crate) struct ThreadPool {
+    queue: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    // Jobs queued or running, and how many of those `try_execute` allows
+    load: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl ThreadPool {
+    /// Pool whose queue grows without limit
+    pub fn new(name: &str, size: usize) -> io::Result<Self> {
+        Self::bounded(name, size, usize::MAX)
+    }
+
+    /// Pool where `try_execute` accepts at most `queue` jobs waiting for a
+    /// worker on top of the ones running; fails if a worker thread cannot
+    /// be spawned, after letting the ones already started exit
+    pub fn bounded(name: &str, size: usize, queue: usize) -> io::Result<Self> {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("{}-{}", name, id))
+                    .spawn(move || Self::work(receiver))
+            })
+            .collect::<io::Result<_>>()?;
+
+        debug!("Started {} pool with {} workers", name, size);
+        Ok(ThreadPool {
+            queue: Some(sender),
+            workers,
+            load: Arc::new(AtomicUsize::new(0)),
+            capacity: size.saturating_add(queue),
+        })
+    }
+
+    /// Queues `job` to run on the next free worker, regardless of capacity
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.load.fetch_add(1, Ordering::SeqCst);
+        self.send(job);
+    }
+
+    /// Queues `job` unless the pool is at capacity. A rejected job is
+    /// dropped along with everything it owns.
+    pub fn try_execute<F: FnOnce() + Send + 'static>(&self, job: F) -> bool {
+        let reserved = self
+            .load
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |load| {
+                (load < self.capacity).then_some(load + 1)
+            });
+        if reserved.is_err() {
+            return false;
+        }
+        self.send(job);
+        true
+    }
+
+    fn send<F: FnOnce() + Send + 'static>(&self, job: F) {
+        // The slot is released once the job has run, panicking or not
+        let load = Arc::clone(&self.load);
+        let job: Job = Box::new(move || {
+            let _release = Release(load);
+            job();
+        });
+
+        let sent = match self.queue {
+            Some(ref sender) => sender.send(job).is_ok(),
+            None => false,
+        };
+        if !sent {
+            error!("Worker pool is gone, dropping job");
+        }
+    }
+
+    fn work(receiver: Arc<Mutex<Receiver<Job>>>) {
+        loop {
+            // Hold the lock only while waiting for the next job
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return, // The pool was dropped
+            };
+            // A panicking job must not take the worker down with it
+            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                error!("A job panicked on a worker thread");
+            }
+        }
+    }
+}
+
+/// Gives a job's slot back to the pool when dropped
+struct Release(Arc<AtomicUsize>);
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel makes every worker exit once the queue is empty
+        drop(self.queue.take());
+        debug!("Closed pool queue for {} workers", self.workers.len());
+    }
+}
@@ -0,0 +1,240 @@
+//! Token-bucket rate limits on requests and request bytes.
+//!
+//! Every connection has its own buckets, and all connections from one IP
+//! address share another set. A request is only charged when every bucket
+//! it touches can pay for it; otherwise it is answered with
+//! `ERROR_CODE_RATE_LIMITED` without reaching a handler.
+
+use super::ServerConfig;
+use crate::{
+    handler::error_response,
+    message::{server_message, ErrorCode},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Addresses tracked before buckets that have refilled are forgotten
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Refills at `rate` tokens per second up to one second's worth.
+///
+/// A request costing more than the whole capacity passes when the bucket
+/// is full and leaves it in debt, so that oversized requests are slowed
+/// down rather than refused forever.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    fn can_take(&self, cost: f64) -> bool {
+        self.tokens >= cost.min(self.rate)
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.tokens -= cost;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.rate
+    }
+}
+
+/// Request and byte buckets; either may be unlimited
+#[derive(Debug, Clone, Default)]
+struct Buckets {
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(requests: Option<u32>, bytes: Option<u64>) -> Self {
+        Buckets {
+            requests: requests.map(|rate| TokenBucket::new(rate.into())),
+            bytes: bytes.map(TokenBucket::new),
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.requests.is_none() && self.bytes.is_none()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut TokenBucket> {
+        self.requests.iter_mut().chain(self.bytes.iter_mut())
+    }
+
+    fn refill(&mut self, now: Instant) {
+        self.iter_mut().for_each(|bucket| bucket.refill(now));
+    }
+
+    /// Why a request of `bytes` cannot be afforded, if it cannot
+    fn shortfall(&self, bytes: f64) -> Option<&'static str> {
+        if self.requests.as_ref().is_some_and(|b| !b.can_take(1.0)) {
+            Some("requests")
+        } else if self.bytes.as_ref().is_some_and(|b| !b.can_take(bytes)) {
+            Some("bytes")
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, bytes: f64) {
+        if let Some(ref mut bucket) = self.requests {
+            bucket.take(1.0);
+        }
+        if let Some(ref mut bucket) = self.bytes {
+            bucket.take(bytes);
+        }
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.iter_mut().all(|bucket| bucket.is_full())
+    }
+}
+
+/// Rates and strikes, as set in the [`ServerConfig`]; `None` is unlimited
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limits {
+    pub request_rate: Option<u32>,
+    pub byte_rate: Option<u64>,
+    pub ip_request_rate: Option<u32>,
+    pub ip_byte_rate: Option<u64>,
+    pub strikes: Option<u32>,
+}
+
+impl Limits {
+    pub fn of(config: &ServerConfig) -> Self {
+        Limits {
+            request_rate: config.request_rate,
+            byte_rate: config.byte_rate,
+            ip_request_rate: config.ip_request_rate,
+            ip_byte_rate: config.ip_byte_rate,
+            strikes: config.rate_limit_strikes,
+        }
+    }
+}
+
+/// Limits shared by every connection of a server, with the per-IP buckets
+#[derive(Debug, Default)]
+pub(crate) struct RateLimits {
+    limits: Limits,
+    per_ip: Mutex<HashMap<IpAddr, Buckets>>,
+}
+
+impl RateLimits {
+    pub fn new(limits: Limits) -> Self {
+        RateLimits {
+            limits,
+            per_ip: Mutex::default(),
+        }
+    }
+
+    /// Limiter for a new connection from `ip`
+    pub fn limiter(self: &Arc<Self>, ip: IpAddr) -> RateLimiter {
+        RateLimiter {
+            shared: Arc::clone(self),
+            ip,
+            own: Buckets::new(self.limits.request_rate, self.limits.byte_rate),
+            strikes: 0,
+        }
+    }
+
+    fn limits_ips(&self) -> bool {
+        self.limits.ip_request_rate.is_some() || self.limits.ip_byte_rate.is_some()
+    }
+}
+
+/// What to do with a request after charging it to the limits
+#[derive(Debug)]
+pub(crate) enum Verdict {
+    Allow,
+    /// Answer with this instead of serving the request
+    Refuse(server_message::Message),
+    /// Answer with this, then close the connection
+    Disconnect(server_message::Message),
+}
+
+/// Buckets of one connection, plus its share of the per-IP ones
+pub(crate) struct RateLimiter {
+    shared: Arc<RateLimits>,
+    ip: IpAddr,
+    own: Buckets,
+    strikes: u32, // Requests refused in a row
+}
+
+impl RateLimiter {
+    /// Charges a request with a payload of `bytes`
+    pub fn check(&mut self, bytes: usize) -> Verdict {
+        if self.own.is_unlimited() && !self.shared.limits_ips() {
+            return Verdict::Allow;
+        }
+        let now = Instant::now();
+        let bytes = bytes as f64;
+        self.own.refill(now);
+        let shortfall = match self.own.shortfall(bytes) {
+            Some(what) => Some(format!("too many {} per second", what)),
+            None => self.charge_ip(now, bytes),
+        };
+
+        let Some(reason) = shortfall else {
+            self.own.take(bytes);
+            self.strikes = 0;
+            return Verdict::Allow;
+        };
+        self.strikes += 1;
+        let response = error_response(ErrorCode::RateLimited, reason);
+        match self.shared.limits.strikes {
+            Some(strikes) if self.strikes >= strikes => Verdict::Disconnect(response),
+            _ => Verdict::Refuse(response),
+        }
+    }
+
+    /// Takes the request from the per-IP buckets, or says why it cannot
+    fn charge_ip(&self, now: Instant, bytes: f64) -> Option<String> {
+        if !self.shared.limits_ips() {
+            return None;
+        }
+        let mut per_ip = self.shared.per_ip.lock().unwrap();
+        if per_ip.len() >= PRUNE_THRESHOLD {
+            // Full buckets are the same as fresh ones
+            per_ip.retain(|_, buckets| {
+                buckets.refill(now);
+                !buckets.is_full()
+            });
+        }
+        let buckets = per_ip.entry(self.ip).or_insert_with(|| {
+            Buckets::new(
+                self.shared.limits.ip_request_rate,
+                self.shared.limits.ip_byte_rate,
+            )
+        });
+        buckets.refill(now);
+        match buckets.shortfall(bytes) {
+            Some(what) => Some(format!("too many {} per second from {}", what, self.ip)),
+            None => {
+                buckets.take(bytes);
+                None
+            }
+        }
+    }
+}
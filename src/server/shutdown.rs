@@ -0,0 +1,150 @@
+//! Graceful shutdown: once the accept loop has stopped, every open connection
+//! stops reading new requests and gets until a deadline to answer the ones it
+//! already has. Whatever is still busy at the deadline is closed.
+
+use log::{debug, warn};
+use std::{
+    collections::HashMap,
+    io,
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// What happened to the connections that were open when the server stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Connections that answered their in-flight requests before the deadline
+    pub drained: usize,
+    /// Connections still busy at the deadline, closed without waiting
+    pub forced: usize,
+}
+
+/// Where `run` is, so that `stop` knows whether there is a drain to wait for
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Phase {
+    Idle,
+    Running,
+    Finished(ShutdownReport),
+}
+
+/// Hands the report of a finished `run` to the threads calling `stop`
+pub(crate) struct Lifecycle {
+    phase: Mutex<Phase>,
+    finished: Condvar,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Lifecycle {
+            phase: Mutex::new(Phase::Idle),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Moves to running, unless a previous run has already finished
+    pub fn start(&self) -> bool {
+        let mut phase = self.phase.lock().unwrap();
+        if let Phase::Finished(_) = *phase {
+            return false;
+        }
+        *phase = Phase::Running;
+        true
+    }
+
+    pub fn finish(&self, report: ShutdownReport) {
+        *self.phase.lock().unwrap() = Phase::Finished(report);
+        self.finished.notify_all();
+    }
+
+    /// Blocks until a running server has drained. A server that was never
+    /// run has nothing to drain and reports so right away.
+    pub fn wait(&self) -> ShutdownReport {
+        let mut phase = self.phase.lock().unwrap();
+        loop {
+            match *phase {
+                Phase::Idle => return ShutdownReport::default(),
+                Phase::Running => phase = self.finished.wait(phase).unwrap(),
+                Phase::Finished(report) => return report,
+            }
+        }
+    }
+}
+
+/// Sockets of the connections accepted by the threaded backend, including
+/// the ones still waiting in the accept queue
+#[derive(Default)]
+pub(crate) struct OpenConnections {
+    streams: Mutex<Streams>,
+    closed: Condvar,
+}
+
+#[derive(Default)]
+struct Streams {
+    open: HashMap<u64, TcpStream>,
+    next_id: u64,
+}
+
+impl OpenConnections {
+    /// Tracks `stream` until the returned registration is dropped
+    pub fn register(self: &Arc<Self>, stream: &TcpStream) -> io::Result<Registration> {
+        let stream = stream.try_clone()?;
+        let mut streams = self.streams.lock().unwrap();
+        let id = streams.next_id;
+        streams.next_id += 1;
+        streams.open.insert(id, stream);
+        Ok(Registration {
+            connections: Arc::clone(self),
+            id,
+        })
+    }
+
+    /// Stops reads on every connection, waits up to `timeout` for them to
+    /// finish and then closes the rest
+    pub fn drain(&self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let mut streams = self.streams.lock().unwrap();
+        let open = streams.open.len();
+        debug!("Draining {} connections", open);
+
+        // A blocked read returns end-of-stream, so each connection thread
+        // finishes the requests it already read and then exits
+        for stream in streams.open.values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+
+        while !streams.open.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            streams = self.closed.wait_timeout(streams, remaining).unwrap().0;
+        }
+
+        let forced = streams.open.len();
+        if forced > 0 {
+            warn!("Closing {} connections still busy at the deadline", forced);
+            for stream in streams.open.values() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        ShutdownReport {
+            drained: open - forced,
+            forced,
+        }
+    }
+}
+
+/// Entry in [`OpenConnections`], removed when the connection is done
+pub(crate) struct Registration {
+    connections: Arc<OpenConnections>,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut streams = self.connections.streams.lock().unwrap();
+        streams.open.remove(&self.id);
+        self.connections.closed.notify_all();
+    }
+}
@@ -0,0 +1,243 @@
+//! rustls session shared by the two halves of a connection.
+//!
+//! The connection thread reads while workers write responses, but a rustls
+//! session is one object. Both halves therefore lock it, and each does its
+//! socket I/O on its own clone of the stream. The reader waits for data
+//! without holding the lock, so responses keep flowing in the meantime.
+//!
+//! The handshake runs before the session is split, so that the identity
+//! in a verified client certificate is known before the first request.
+
+use super::{ConfigError, ServerConfig};
+use crate::handler::PeerIdentity;
+use log::{debug, warn};
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConnection,
+};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+/// Size of the chunk used when pulling TLS records from the socket
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Builds the rustls configuration, or `None` if TLS is not configured
+pub(crate) fn load(
+    config: &ServerConfig,
+) -> Result<Option<Arc<rustls::ServerConfig>>, ConfigError> {
+    let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else {
+        return Ok(None);
+    };
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error("tls_cert", cert, e))?;
+    if chain.is_empty() {
+        return Err(bad_file("tls_cert", cert, "no certificate found"));
+    }
+    let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| pem_error("tls_key", key, e))?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| bad_file("tls_cert", cert, e.to_string()))?;
+    let builder = match config.tls_client_ca {
+        Some(ref ca) => builder.with_client_cert_verifier(client_verifier(ca)?),
+        None => builder.with_no_client_auth(),
+    };
+    let tls = builder
+        .with_single_cert(chain, key_der)
+        .map_err(|e| bad_file("tls_key", key, e.to_string()))?;
+    debug!("Loaded TLS certificate from {}", cert.display());
+    Ok(Some(Arc::new(tls)))
+}
+
+/// Accepts only clients with a certificate signed by a CA in `ca`
+fn client_verifier(
+    ca: &Path,
+) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>, ConfigError> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca).map_err(|e| pem_error("tls_client_ca", ca, e))? {
+        let cert = cert.map_err(|e| pem_error("tls_client_ca", ca, e))?;
+        roots
+            .add(cert)
+            .map_err(|e| bad_file("tls_client_ca", ca, e.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(bad_file("tls_client_ca", ca, "no certificate found"));
+    }
+    debug!("Requiring client certificates signed by {}", ca.display());
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider())
+        .build()
+        .map_err(|e| bad_file("tls_client_ca", ca, e.to_string()))
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn pem_error(key: &str, path: &Path, e: rustls::pki_types::pem::Error) -> ConfigError {
+    match e {
+        rustls::pki_types::pem::Error::Io(source) => ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        },
+        e => bad_file(key, path, e.to_string()),
+    }
+}
+
+fn bad_file(key: &str, path: &Path, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Value {
+        key: key.to_string(),
+        value: path.display().to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Completes the handshake on `stream` and splits the session into two
+/// halves, returning the client's identity if it sent a certificate
+pub(crate) fn split(
+    config: &Arc<rustls::ServerConfig>,
+    stream: &TcpStream,
+) -> io::Result<(Reader, Writer, Option<PeerIdentity>)> {
+    let mut session = ServerConnection::new(Arc::clone(config)).map_err(io::Error::other)?;
+    let mut socket = stream.try_clone()?;
+    while session.is_handshaking() {
+        // Fails with the reason, e.g. an untrusted client certificate,
+        // after telling the client
+        session.complete_io(&mut socket)?;
+    }
+    let identity = session
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .and_then(identity);
+    let session = Arc::new(Mutex::new(session));
+    let reader = Reader {
+        session: Arc::clone(&session),
+        socket: stream.try_clone()?,
+        chunk: vec![0; READ_CHUNK_SIZE],
+    };
+    let writer = Writer { session, socket };
+    Ok((reader, writer, identity))
+}
+
+/// Reads the subject and alternative names of a verified certificate
+fn identity(cert: &CertificateDer<'_>) -> Option<PeerIdentity> {
+    let cert = match X509Certificate::from_der(cert) {
+        Ok((_, cert)) => cert,
+        Err(e) => {
+            // rustls accepted it, so this is unlikely
+            warn!("Failed to parse client certificate: {}", e);
+            return None;
+        }
+    };
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string);
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(general_name)
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some(PeerIdentity {
+        subject: cert.subject().to_string(),
+        common_name,
+        sans,
+    })
+}
+
+fn general_name(name: &GeneralName<'_>) -> Option<String> {
+    match *name {
+        GeneralName::DNSName(name) | GeneralName::URI(name) | GeneralName::RFC822Name(name) => {
+            Some(name.to_string())
+        }
+        GeneralName::IPAddress(bytes) => {
+            let ip = match bytes.len() {
+                4 => IpAddr::from(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+                16 => IpAddr::from(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+                _ => return None,
+            };
+            Some(ip.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Sends whatever TLS records the session has queued
+fn send_pending(session: &mut ServerConnection, socket: &mut TcpStream) -> io::Result<()> {
+    while session.wants_write() {
+        session.write_tls(socket)?;
+    }
+    Ok(())
+}
+
+pub(crate) struct Reader {
+    session: Arc<Mutex<ServerConnection>>,
+    socket: TcpStream,
+    chunk: Vec<u8>,
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.session.lock().unwrap().reader().read(buf) {
+                Ok(n) => return Ok(n),
+                // The peer hung up without close_notify; the framing layer
+                // notices if that cut a frame short
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            // No plaintext buffered: wait for more records, unlocked
+            let n = self.socket.read(&mut self.chunk)?;
+            let mut session = self.session.lock().unwrap();
+            let mut records = &self.chunk[..n];
+            loop {
+                session.read_tls(&mut records)?;
+                if let Err(e) = session.process_new_packets() {
+                    // Tell the peer why before giving up
+                    let _ = send_pending(&mut session, &mut self.socket);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                if records.is_empty() {
+                    break;
+                }
+            }
+            // Handshake messages and alerts
+            send_pending(&mut session, &mut self.socket)?;
+        }
+    }
+}
+
+pub(crate) struct Writer {
+    session: Arc<Mutex<ServerConnection>>,
+    socket: TcpStream,
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = self.session.lock().unwrap();
+        let n = session.writer().write(buf)?;
+        send_pending(&mut session, &mut self.socket)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = self.session.lock().unwrap();
+        session.writer().flush()?;
+        send_pending(&mut session, &mut self.socket)?;
+        self.socket.flush()
+    }
+}
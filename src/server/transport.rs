@@ -0,0 +1,109 @@
+//! Byte transport of accepted connections: plain TCP, or TLS with the
+//! `tls` feature.
+
+use super::ConfigError;
+use super::ServerConfig;
+use crate::handler::ConnectionContext;
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+#[cfg(feature = "tls")]
+use super::tls;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+/// Wraps accepted streams in the transport the configuration asks for
+#[derive(Clone, Default)]
+pub(crate) struct Acceptor {
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl Acceptor {
+    /// Loads the certificate and key if TLS is configured
+    pub fn new(config: &ServerConfig) -> Result<Self, ConfigError> {
+        #[cfg(feature = "tls")]
+        {
+            Ok(Acceptor {
+                tls: tls::load(config)?,
+            })
+        }
+        #[cfg(not(feature = "tls"))]
+        {
+            if config.tls_enabled() {
+                return Err(ConfigError::Invalid {
+                    key: "tls_cert",
+                    reason: "TLS needs the tls feature",
+                });
+            }
+            Ok(Acceptor {})
+        }
+    }
+
+    /// Splits `stream` into the half read by the connection thread and the
+    /// half the workers write responses to, along with what is known about
+    /// the peer. With TLS this runs the handshake first.
+    pub fn split(
+        &self,
+        stream: &TcpStream,
+        addr: SocketAddr,
+    ) -> io::Result<(Reader, Writer, ConnectionContext)> {
+        let context = ConnectionContext::new(addr);
+        #[cfg(feature = "tls")]
+        if let Some(ref config) = self.tls {
+            let (reader, writer, identity) = tls::split(config, stream)?;
+            let context = match identity {
+                Some(identity) => context.with_identity(identity),
+                None => context,
+            };
+            return Ok((Reader::Tls(reader), Writer::Tls(writer), context));
+        }
+        Ok((
+            Reader::Plain(stream.try_clone()?),
+            Writer::Plain(stream.try_clone()?),
+            context,
+        ))
+    }
+}
+
+pub(crate) enum Reader {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(tls::Reader),
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Reader::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Reader::Tls(reader) => reader.read(buf),
+        }
+    }
+}
+
+pub(crate) enum Writer {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(tls::Writer),
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Writer::Tls(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Writer::Tls(writer) => writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use embedded_recruitment_task::{
+    client::Client,
+    server::{Backend, IpNet, MetricsSnapshot, ServerBuilder, ServerHandle},
+};
+use std::{
+    io::{ErrorKind, Read},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+fn create_server(builder: ServerBuilder) -> ServerHandle {
+    builder
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+fn net(text: &str) -> IpNet {
+    text.parse().unwrap()
+}
+
+fn connect(server: &ServerHandle) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().expect("Failed to connect to the server");
+    client
+}
+
+/// True if the server closes a fresh connection without serving it; a
+/// server still waiting for a request when the read times out did not
+fn is_refused(server: &ServerHandle) -> bool {
+    let mut stream = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut buffer = [0u8; 1];
+    match stream.read(&mut buffer) {
+        Ok(0) => true,
+        Err(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+        ),
+        Ok(_) => false,
+    }
+}
+
+fn wait_for_metrics(
+    server: &ServerHandle,
+    done: impl Fn(&MetricsSnapshot) -> bool,
+) -> MetricsSnapshot {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let metrics = server.metrics();
+        if done(&metrics) || Instant::now() > deadline {
+            return metrics;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_denied_networks_are_closed() {
+    for backend in [Backend::Threaded, Backend::EventLoop] {
+        let server = create_server(
+            ServerBuilder::new("localhost:0")
+                .with_backend(backend)
+                .with_deny(net("127.0.0.0/8"))
+                .with_deny(net("::1/128")),
+        );
+        assert!(is_refused(&server));
+
+        let metrics = wait_for_metrics(&server, |m| m.connections_denied == 1);
+        assert_eq!(metrics.connections_denied, 1);
+        assert_eq!(metrics.connections_accepted, 0);
+    }
+}
+
+#[test]
+fn test_only_allowed_networks_are_served() {
+    let server = create_server(ServerBuilder::new("localhost:0").with_allow(net("10.0.0.0/8")));
+    assert!(is_refused(&server));
+    assert_eq!(
+        wait_for_metrics(&server, |m| m.connections_denied == 1).connections_denied,
+        1
+    );
+
+    let server = create_server(
+        ServerBuilder::new("localhost:0")
+            .with_allow(net("127.0.0.0/8"))
+            .with_allow(net("::1/128")),
+    );
+    assert_eq!(connect(&server).echo("allowed").unwrap(), "allowed");
+
+    // Denying wins over allowing
+    let server = create_server(
+        ServerBuilder::new("localhost:0")
+            .with_allow(net("127.0.0.0/8"))
+            .with_deny(net("127.0.0.1/32"))
+            .with_deny(net("::1/128")),
+    );
+    assert!(is_refused(&server));
+    assert_eq!(
+        wait_for_metrics(&server, |m| m.connections_denied == 1).connections_denied,
+        1
+    );
+}
+
+#[test]
+fn test_connections_per_ip_are_capped() {
+    for backend in [Backend::Threaded, Backend::EventLoop] {
+        let server = create_server(
+            ServerBuilder::new("localhost:0")
+                .with_backend(backend)
+                .with_max_connections_per_ip(2),
+        );
+        let mut first = connect(&server);
+        let mut second = connect(&server);
+        assert_eq!(first.echo("one").unwrap(), "one");
+        assert_eq!(second.echo("two").unwrap(), "two");
+
+        assert!(is_refused(&server));
+        let metrics = wait_for_metrics(&server, |m| m.connections_refused == 1);
+        assert_eq!(metrics.connections_refused, 1);
+        assert_eq!(metrics.connections_denied, 0);
+
+        // A closed connection frees its place
+        first.disconnect().unwrap();
+        wait_for_metrics(&server, |m| m.connections_active == 1);
+        assert_eq!(connect(&server).echo("three").unwrap(), "three");
+    }
+}
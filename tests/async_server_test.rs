@@ -0,0 +1,425 @@
+#![cfg(feature = "tokio")]
+
+use embedded_recruitment_task::{
+    framing::{encode_frame, FrameDecoder, DEFAULT_MAX_FRAME_SIZE},
+    handler::{self, MessageKind},
+    message::{
+        client_message, server_message, AddRequest, AuthRequest, ClientMessage, EchoMessage,
+        ErrorCode, ServerMessage,
+    },
+    server::{AsyncServer, ConfigError, ServerConfig, ShutdownReport, StaticTokens},
+};
+use prost::Message;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinHandle,
+};
+
+async fn setup_server(server: AsyncServer) -> (Arc<AsyncServer>, JoinHandle<ShutdownReport>) {
+    let server = Arc::new(server);
+    let handle = {
+        let server = server.clone();
+        tokio::spawn(async move { server.run().await.expect("Server encountered an error") })
+    };
+    (server, handle)
+}
+
+/// Minimal async client speaking the framed protocol
+struct TestClient {
+    stream: TcpStream,
+    frames: FrameDecoder,
+    next_request_id: u64,
+}
+
+impl TestClient {
+    async fn connect(server: &AsyncServer) -> Self {
+        TestClient {
+            stream: TcpStream::connect(server.local_addr()).await.unwrap(),
+            frames: FrameDecoder::default(),
+            next_request_id: 1,
+        }
+    }
+
+    async fn send(&mut self, message: client_message::Message) -> u64 {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let envelope = ClientMessage {
+            message: Some(message),
+            request_id,
+        };
+        let frame = encode_frame(&envelope, DEFAULT_MAX_FRAME_SIZE).unwrap();
+        self.stream.write_all(&frame).await.unwrap();
+        request_id
+    }
+
+    async fn receive(&mut self) -> ServerMessage {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(frame) = self.frames.decode().unwrap() {
+                return ServerMessage::decode(frame.as_slice()).unwrap();
+            }
+            let n = tokio::time::timeout(Duration::from_secs(1), self.stream.read(&mut chunk))
+                .await
+                .expect("Timed out waiting for a response")
+                .unwrap();
+            assert!(n > 0, "Server closed the connection");
+            self.frames.extend(&chunk[..n]);
+        }
+    }
+}
+
+fn echo(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn test_async_echo_and_add() {
+    let (server, handle) = setup_server(AsyncServer::bind("localhost:0").await.unwrap()).await;
+    let mut client = TestClient::connect(&server).await;
+
+    let request_id = client.send(echo("Hello, World!")).await;
+    let response = client.receive().await;
+    assert_eq!(response.request_id, request_id);
+    match response.message {
+        Some(server_message::Message::EchoMessage(echo)) => {
+            assert_eq!(echo.content, "Hello, World!")
+        }
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+
+    client
+        .send(client_message::Message::AddRequest(AddRequest {
+            a: 10,
+            b: 20,
+        }))
+        .await;
+    match client.receive().await.message {
+        Some(server_message::Message::AddResponse(add)) => assert_eq!(add.result, 30),
+        other => panic!("Expected AddResponse, got {:?}", other),
+    }
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_reports_errors() {
+    let (server, handle) = setup_server(AsyncServer::bind("localhost:0").await.unwrap()).await;
+    let mut client = TestClient::connect(&server).await;
+
+    client
+        .send(client_message::Message::AddRequest(AddRequest {
+            a: i32::MAX,
+            b: 1,
+        }))
+        .await;
+    match client.receive().await.message {
+        Some(server_message::Message::ErrorResponse(error)) => {
+            assert_eq!(error.code(), ErrorCode::Overflow)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+
+    // Undecodable payload inside a valid frame
+    client.stream.write_all(&[2, 0xff, 0xff]).await.unwrap();
+    match client.receive().await.message {
+        Some(server_message::Message::ErrorResponse(error)) => {
+            assert_eq!(error.code(), ErrorCode::DecodeFailure)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_answers_out_of_order() {
+    let server = AsyncServer::bind("localhost:0")
+        .await
+        .unwrap()
+        .with_handler(MessageKind::Echo, |request| {
+            if let client_message::Message::EchoMessage(ref echo) = request {
+                if echo.content == "slow" {
+                    std::thread::sleep(Duration::from_millis(300));
+                }
+            }
+            handler::echo(request)
+        });
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+
+    let slow = client.send(echo("slow")).await;
+    let fast = client.send(echo("fast")).await;
+    assert_eq!(client.receive().await.request_id, fast);
+    assert_eq!(client.receive().await.request_id, slow);
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_requires_authentication() {
+    let tokens = StaticTokens::new().with_token("t0k3n", "device-42", None);
+    let server = AsyncServer::bind("localhost:0")
+        .await
+        .unwrap()
+        .with_authenticator(tokens);
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+
+    client.send(echo("too early")).await;
+    match client.receive().await.message {
+        Some(server_message::Message::ErrorResponse(error)) => {
+            assert_eq!(error.code(), ErrorCode::Unauthenticated)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+
+    // Requests pipelined behind a successful AuthRequest are served
+    let auth = client_message::Message::AuthRequest(AuthRequest {
+        token: "t0k3n".to_string(),
+    });
+    client.send(auth).await;
+    client.send(echo("in time")).await;
+    match client.receive().await.message {
+        Some(server_message::Message::AuthResponse(auth)) => {
+            assert_eq!(auth.principal, "device-42")
+        }
+        other => panic!("Expected AuthResponse, got {:?}", other),
+    }
+    match client.receive().await.message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "in time"),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_rate_limits_requests() {
+    let server = AsyncServer::from_config(ServerConfig {
+        request_rate: Some(2),
+        rate_limit_strikes: Some(2),
+        ..ServerConfig::new("localhost:0")
+    })
+    .await
+    .unwrap();
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+
+    for n in 0..4 {
+        client.send(echo(&n.to_string())).await;
+    }
+    let mut codes = Vec::new();
+    for _ in 0..4 {
+        match client.receive().await.message {
+            Some(server_message::Message::EchoMessage(_)) => {}
+            Some(server_message::Message::ErrorResponse(error)) => codes.push(error.code()),
+            other => panic!("Unexpected response {:?}", other),
+        }
+    }
+    assert_eq!(codes, [ErrorCode::RateLimited, ErrorCode::RateLimited]);
+
+    // The second refusal in a row closed the connection
+    let mut buf = [0; 1];
+    assert_eq!(client.stream.read(&mut buf).await.unwrap(), 0);
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_connection_limits() {
+    let server = AsyncServer::from_config(ServerConfig {
+        max_connections: 1,
+        ..ServerConfig::new("127.0.0.1:0")
+    })
+    .await
+    .unwrap();
+    let (server, handle) = setup_server(server).await;
+    let mut first = TestClient::connect(&server).await;
+    first.send(echo("first")).await;
+    first.receive().await;
+
+    // Beyond the cap a connection is closed without being served
+    let mut second = TestClient::connect(&server).await;
+    let mut buf = [0; 1];
+    assert_eq!(second.stream.read(&mut buf).await.unwrap(), 0);
+    assert_eq!(server.metrics().connections_refused, 1);
+
+    server.stop().await;
+    handle.await.unwrap();
+
+    let server = AsyncServer::from_config(ServerConfig {
+        allow: vec!["10.0.0.0/8".parse().unwrap()],
+        ..ServerConfig::new("127.0.0.1:0")
+    })
+    .await
+    .unwrap();
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+    assert_eq!(client.stream.read(&mut buf).await.unwrap(), 0);
+    assert_eq!(server.metrics().connections_denied, 1);
+
+    server.stop().await;
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_config_is_validated() {
+    let config = ServerConfig {
+        max_frame_size: 0,
+        ..ServerConfig::new("localhost:0")
+    };
+    assert!(matches!(
+        AsyncServer::from_config(config).await,
+        Err(ConfigError::Invalid {
+            key: "max_frame_size",
+            ..
+        })
+    ));
+
+    let config = ServerConfig {
+        max_connections: 3,
+        ..ServerConfig::new("localhost:0")
+    };
+    let server = AsyncServer::from_config(config.clone()).await.unwrap();
+    assert_eq!(server.config(), &config);
+}
+
+#[tokio::test]
+async fn test_async_stop_before_run() {
+    let server = AsyncServer::bind("localhost:0").await.unwrap();
+    assert_eq!(server.stop().await, ShutdownReport::default());
+    // Returns right away instead of accepting connections
+    tokio::time::timeout(Duration::from_secs(1), server.run())
+        .await
+        .expect("run did not observe the earlier stop")
+        .unwrap();
+    assert!(server.run().await.is_err(), "a stopped server runs again");
+}
+
+/// Server whose echo handler takes `delay` before answering
+async fn slow_server(delay: Duration, shutdown_timeout: Duration) -> AsyncServer {
+    AsyncServer::from_config(ServerConfig {
+        shutdown_timeout,
+        ..ServerConfig::new("localhost:0")
+    })
+    .await
+    .unwrap()
+    .with_handler(MessageKind::Echo, move |request| {
+        std::thread::sleep(delay);
+        handler::echo(request)
+    })
+}
+
+#[tokio::test]
+async fn test_async_stop_drains_requests_in_flight() {
+    let server = slow_server(Duration::from_millis(200), Duration::from_secs(5)).await;
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+    let mut idle = TestClient::connect(&server).await;
+
+    client.send(echo("slow")).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let report = server.stop().await;
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 2,
+            forced: 0
+        }
+    );
+    assert_eq!(handle.await.unwrap(), report);
+
+    // The request read before the stop was still answered
+    match client.receive().await.message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "slow"),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+    let mut buf = [0; 1];
+    assert_eq!(idle.stream.read(&mut buf).await.unwrap(), 0);
+    assert_eq!(server.metrics().connections_active, 0);
+}
+
+#[tokio::test]
+async fn test_async_stop_aborts_connections_at_the_deadline() {
+    let server = slow_server(Duration::from_millis(1000), Duration::from_millis(100)).await;
+    let (server, handle) = setup_server(server).await;
+    let mut client = TestClient::connect(&server).await;
+
+    client.send(echo("too slow")).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let report = server.stop().await;
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 0,
+            forced: 1
+        }
+    );
+    handle.await.unwrap();
+
+    // Closed without the response
+    let mut buf = [0; 1];
+    let closed = tokio::time::timeout(Duration::from_millis(500), client.stream.read(&mut buf))
+        .await
+        .expect("connection left open after the deadline");
+    assert!(matches!(closed, Ok(0) | Err(_)));
+    assert_eq!(server.metrics().connections_active, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_stops_reading_from_clients_that_never_read() {
+    let server = AsyncServer::from_config(ServerConfig {
+        shutdown_timeout: Duration::from_millis(100),
+        ..ServerConfig::new("localhost:0")
+    })
+    .await
+    .unwrap()
+    .with_authenticator(StaticTokens::new().with_token("t0k3n", "device-42", None));
+    let (server, handle) = setup_server(server).await;
+
+    // Every request is refused as unauthenticated, and the client never
+    // reads the answers
+    let request = ClientMessage {
+        message: Some(echo("ping")),
+        request_id: 1,
+    };
+    let frame = encode_frame(&request, DEFAULT_MAX_FRAME_SIZE).unwrap();
+    let chunk = frame.repeat(64 * 1024 / frame.len());
+    let mut flood = TcpStream::connect(server.local_addr()).await.unwrap();
+    let mut sent = 0;
+    while sent < 32 * 1024 * 1024 {
+        let write = tokio::time::timeout(Duration::from_millis(500), flood.write_all(&chunk));
+        match write.await {
+            Ok(Ok(())) => sent += chunk.len(),
+            _ => break,
+        }
+    }
+    assert!(
+        sent < 32 * 1024 * 1024,
+        "The server kept reading {} bytes without its answers being read",
+        sent
+    );
+
+    // Other clients are still served
+    let mut client = TestClient::connect(&server).await;
+    client
+        .send(client_message::Message::AuthRequest(AuthRequest {
+            token: "t0k3n".to_string(),
+        }))
+        .await;
+    client.receive().await;
+    client.send(echo("ping")).await;
+    client.receive().await;
+
+    server.stop().await;
+    handle.await.unwrap();
+}
@@ -0,0 +1,210 @@
+use embedded_recruitment_task::{
+    client::{Client, ClientPool, PoolConfig},
+    handler::{with_context, ConnectionContext, MessageKind},
+    message::{server_message, EchoMessage, ErrorCode},
+    server::{
+        AuthError, Authenticator, Backend, ConfigError, ServerBuilder, ServerHandle, StaticTokens,
+    },
+    Error,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    process,
+    time::{Duration, SystemTime},
+};
+
+/// Token file removed again when dropped
+struct TokenFile(PathBuf);
+
+impl TokenFile {
+    fn write(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ert-{}-{}.tokens", name, process::id()));
+        fs::write(&path, contents).unwrap();
+        TokenFile(path)
+    }
+}
+
+impl Drop for TokenFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+const TOKENS: &str = "\
+# token   principal  expires
+t0k3n     device-42
+0ld       retired    1
+";
+
+fn create_server(tokens: &TokenFile, backend: Backend) -> ServerHandle {
+    ServerBuilder::new("localhost:0")
+        .with_backend(backend)
+        .with_auth_tokens(&tokens.0)
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+fn connect(server: &ServerHandle) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().expect("Failed to connect to the server");
+    client
+}
+
+fn assert_unauthenticated<T: std::fmt::Debug>(result: Result<T, Error>, expected: &str) {
+    match result {
+        Err(Error::Server { code, detail }) => {
+            assert_eq!(code, ErrorCode::Unauthenticated);
+            assert!(detail.contains(expected), "unexpected detail {:?}", detail);
+        }
+        other => panic!("Expected an unauthenticated error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_requests_before_authentication_are_rejected() {
+    let tokens = TokenFile::write("before", TOKENS);
+    let server = create_server(&tokens, Backend::Threaded);
+    let mut client = connect(&server);
+
+    assert_unauthenticated(client.echo("hello"), "authenticate before");
+    assert_unauthenticated(client.add(1, 2), "authenticate before");
+
+    assert_eq!(client.authenticate("t0k3n").unwrap(), "device-42");
+    assert_eq!(client.echo("hello").unwrap(), "hello");
+    assert_unauthenticated(client.authenticate("t0k3n"), "already authenticated");
+}
+
+#[test]
+fn test_rejected_expired_and_missing_tokens() {
+    let tokens = TokenFile::write("refused", TOKENS);
+    let server = create_server(&tokens, Backend::Threaded);
+    let mut client = connect(&server);
+
+    assert_unauthenticated(client.authenticate("guess"), "unknown token");
+    assert_unauthenticated(client.authenticate("0ld"), "token expired");
+    assert_unauthenticated(client.authenticate(""), "no token given");
+
+    // Failed attempts leave the connection unauthenticated, not closed
+    assert_unauthenticated(client.echo("hello"), "authenticate before");
+    assert_eq!(client.authenticate("t0k3n").unwrap(), "device-42");
+}
+
+#[test]
+fn test_auth_token_is_sent_on_connect() {
+    let tokens = TokenFile::write("connect", TOKENS);
+    let server = create_server(&tokens, Backend::EventLoop);
+
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_auth_token("t0k3n");
+    client.connect().unwrap();
+    assert_eq!(client.echo("hello").unwrap(), "hello");
+
+    // And again on every new connection
+    client.connect().unwrap();
+    assert_eq!(client.add(2, 40).unwrap(), 42);
+
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_auth_token("0ld");
+    assert_unauthenticated(client.connect(), "token expired");
+    assert!(!client.is_connected());
+}
+
+#[test]
+fn test_handlers_see_the_principal() {
+    let whoami = with_context(|context: &ConnectionContext, _| {
+        server_message::Message::EchoMessage(EchoMessage {
+            content: context.principal().unwrap_or("nobody").to_string(),
+        })
+    });
+    let authenticator = |token: &str| match token {
+        "letmein" => Ok("operator".to_string()),
+        _ => Err(AuthError::Denied("try letmein".to_string())),
+    };
+    let server = ServerBuilder::new("localhost:0")
+        .with_authenticator(authenticator)
+        .with_handler(MessageKind::Echo, whoami)
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server");
+
+    let mut client = connect(&server);
+    assert_unauthenticated(client.authenticate("open sesame"), "try letmein");
+    client.authenticate("letmein").unwrap();
+    assert_eq!(client.echo("who am I?").unwrap(), "operator");
+}
+
+#[test]
+fn test_pooled_clients_authenticate() {
+    let tokens = TokenFile::write("pool", TOKENS);
+    let server = create_server(&tokens, Backend::Threaded);
+    let config = PoolConfig {
+        min_connections: 1,
+        health_check_after: Some(Duration::ZERO),
+        auth_token: Some("t0k3n".to_string()),
+        ..Default::default()
+    };
+    let pool = ClientPool::new("localhost", server.get_port().into(), 1000, config).unwrap();
+
+    for _ in 0..3 {
+        let mut client = pool.checkout().unwrap();
+        assert_eq!(client.echo("pooled").unwrap(), "pooled");
+    }
+    assert_eq!(pool.status().open, 1);
+}
+
+#[test]
+fn test_token_file_errors() {
+    let missing = std::env::temp_dir().join("ert-missing.tokens");
+    let error = StaticTokens::from_file(&missing).unwrap_err();
+    assert!(matches!(error, ConfigError::Read { ref path, .. } if *path == missing));
+
+    for (name, contents, reason) in [
+        ("empty", "# nothing here\n", "no tokens found"),
+        ("fields", "t0k3n\n", "line 1: expected a token"),
+        (
+            "expiry",
+            "t0k3n device-42 tomorrow\n",
+            "line 1: invalid expiry",
+        ),
+        ("duplicate", "t0k3n a\nt0k3n b\n", "line 2: duplicate token"),
+    ] {
+        let tokens = TokenFile::write(name, contents);
+        match StaticTokens::from_file(&tokens.0) {
+            Err(ConfigError::Value {
+                key, reason: got, ..
+            }) => {
+                assert_eq!(key, "auth_tokens");
+                assert!(got.starts_with(reason), "{}: got {:?}", name, got);
+            }
+            other => panic!("{}: expected a value error, got {:?}", name, other),
+        }
+    }
+
+    // Building a server loads the file right away
+    let error = ServerBuilder::new("localhost:0")
+        .with_auth_tokens(&missing)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(error, ConfigError::Read { .. }));
+}
+
+#[test]
+fn test_static_tokens_in_code() {
+    let tomorrow = SystemTime::now() + Duration::from_secs(86_400);
+    let yesterday = SystemTime::now() - Duration::from_secs(86_400);
+    let tokens = StaticTokens::new()
+        .with_token("s3cr3t", "alice", Some(tomorrow))
+        .with_token("b", "bob", Some(yesterday));
+
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens.authenticate("s3cr3t"), Ok("alice".to_string()));
+    assert_eq!(tokens.authenticate("b"), Err(AuthError::Expired));
+    assert_eq!(tokens.authenticate("c"), Err(AuthError::Unknown));
+    // Debug output never shows the tokens
+    assert!(!format!("{:?}", tokens).contains("s3cr3t"));
+}
@@ -0,0 +1,222 @@
+use embedded_recruitment_task::{
+    client::{ClientPool, PoolConfig, PoolStatus},
+    handler::{self, MessageKind},
+    message::{client_message, EchoMessage},
+    server::{Server, ServerBuilder, ServerHandle},
+    Error,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+fn create_server() -> ServerHandle {
+    Server::new("localhost:0")
+        .and_then(Server::spawn)
+        .expect("Failed to start server")
+}
+
+fn create_pool(server: &ServerHandle, config: PoolConfig) -> ClientPool {
+    ClientPool::new("localhost", server.get_port().into(), 1000, config)
+        .expect("Failed to create the pool")
+}
+
+#[test]
+fn test_opens_min_connections_up_front() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            min_connections: 3,
+            ..PoolConfig::default()
+        },
+    );
+    assert_eq!(pool.status(), PoolStatus { open: 3, idle: 3 });
+
+    let mut client = pool.checkout().unwrap();
+    assert_eq!(client.echo("pooled").unwrap(), "pooled");
+    assert_eq!(pool.status(), PoolStatus { open: 3, idle: 2 });
+    drop(client);
+    assert_eq!(pool.status(), PoolStatus { open: 3, idle: 3 });
+}
+
+#[test]
+fn test_connections_are_reused() {
+    let server = create_server();
+    let pool = create_pool(&server, PoolConfig::default());
+
+    for i in 0..10 {
+        assert_eq!(
+            pool.checkout().unwrap().add(i, i).unwrap(),
+            i64::from(2 * i)
+        );
+    }
+    assert_eq!(pool.status(), PoolStatus { open: 1, idle: 1 });
+}
+
+#[test]
+fn test_many_threads_share_a_pool() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            max_connections: 4,
+            ..PoolConfig::default()
+        },
+    );
+
+    let workers: Vec<_> = (0..16)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for j in 0..20 {
+                    let mut client = pool.checkout().unwrap();
+                    assert_eq!(client.add(i, j).unwrap(), i64::from(i + j));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let status = pool.status();
+    assert!(status.open <= 4);
+    assert_eq!(status.open, status.idle);
+}
+
+#[test]
+fn test_checkout_times_out_when_exhausted() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            max_connections: 1,
+            checkout_timeout: Duration::from_millis(100),
+            ..PoolConfig::default()
+        },
+    );
+
+    let held = pool.checkout().unwrap();
+    let start = Instant::now();
+    match pool.checkout() {
+        Err(Error::PoolTimeout { waited }) => {
+            assert_eq!(waited, Duration::from_millis(100))
+        }
+        other => panic!("Expected a pool timeout, got {:?}", other.err()),
+    }
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // A returned connection wakes up a waiting checkout
+    let waiter = {
+        let pool = ClientPool::clone(&pool);
+        thread::spawn(move || pool.checkout().map(|mut client| client.echo("woken")))
+    };
+    thread::sleep(Duration::from_millis(20));
+    drop(held);
+    assert_eq!(waiter.join().unwrap().unwrap().unwrap(), "woken");
+}
+
+#[test]
+fn test_idle_connections_are_evicted() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            min_connections: 1,
+            idle_timeout: Some(Duration::from_millis(50)),
+            ..PoolConfig::default()
+        },
+    );
+
+    let clients: Vec<_> = (0..3).map(|_| pool.checkout().unwrap()).collect();
+    drop(clients);
+    assert_eq!(pool.status(), PoolStatus { open: 3, idle: 3 });
+
+    // Closed in the background, without another checkout
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(pool.status(), PoolStatus { open: 1, idle: 1 });
+    assert_eq!(pool.evict_idle(), 0);
+}
+
+#[test]
+fn test_broken_connections_are_replaced() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            min_connections: 2,
+            health_check_after: Some(Duration::ZERO),
+            ..PoolConfig::default()
+        },
+    );
+    let addr = server.local_addr();
+    server.stop();
+    drop(server);
+
+    // Both idle connections were closed by the server and no server is up
+    assert!(pool.checkout().is_err());
+    assert_eq!(pool.status(), PoolStatus { open: 0, idle: 0 });
+
+    let _server = Server::new(&addr.to_string())
+        .and_then(Server::spawn)
+        .unwrap();
+    assert_eq!(pool.checkout().unwrap().echo("fresh").unwrap(), "fresh");
+}
+
+#[test]
+fn test_discarded_connections_free_their_slot() {
+    let server = create_server();
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            max_connections: 1,
+            ..PoolConfig::default()
+        },
+    );
+
+    pool.checkout().unwrap().discard();
+    assert_eq!(pool.status(), PoolStatus { open: 0, idle: 0 });
+
+    // A connection with a response left unread is not handed out again
+    let mut client = pool.checkout().unwrap();
+    let unread = client_message::Message::EchoMessage(EchoMessage {
+        content: "unread".to_string(),
+    });
+    client.send(unread).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    drop(client);
+    assert_eq!(pool.status(), PoolStatus { open: 0, idle: 0 });
+}
+
+#[test]
+fn test_connections_awaiting_a_response_are_discarded() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_handler(MessageKind::Echo, |request| {
+            thread::sleep(Duration::from_millis(200));
+            handler::echo(request)
+        })
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server");
+    let pool = create_pool(
+        &server,
+        PoolConfig {
+            max_connections: 1,
+            ..PoolConfig::default()
+        },
+    );
+
+    // Dropped before the response arrives, which the next borrower must
+    // not receive in place of its own
+    let mut client = pool.checkout().unwrap();
+    let slow = client_message::Message::EchoMessage(EchoMessage {
+        content: "slow".to_string(),
+    });
+    client.send(slow).unwrap();
+    drop(client);
+    assert_eq!(pool.status(), PoolStatus { open: 0, idle: 0 });
+
+    assert_eq!(pool.checkout().unwrap().echo("mine").unwrap(), "mine");
+}
@@ -1,32 +1,22 @@
 use embedded_recruitment_task::{
+    client::Client,
     message::{client_message, server_message, AddRequest, EchoMessage},
-    server::Server,
+    server::{Server, ServerHandle},
 };
-use std::{
-    sync::Arc,
-    thread::{self, JoinHandle},
-};
-
-mod client;
-
-fn setup_server_thread(server: Arc<Server>) -> JoinHandle<()> {
-    thread::spawn(move || {
-        server.run().expect("Server encountered an error");
-    })
-}
 
-fn create_server() -> Arc<Server> {
-    Arc::new(Server::new("localhost:0").expect("Failed to start server"))
+fn create_server() -> ServerHandle {
+    Server::new("localhost:0")
+        .and_then(Server::spawn)
+        .expect("Failed to start server")
 }
 
 #[test]
 fn test_client_connection() {
     // Set up the server in a separate thread
     let server = create_server();
-    let handle = setup_server_thread(server.clone());
     let server_port = server.get_port();
     // Create and connect the client
-    let mut client = client::Client::new("localhost", server_port.into(), 1000);
+    let mut client = Client::new("localhost", server_port.into(), 1000);
     assert!(client.connect().is_ok(), "Failed to connect to the server");
 
     // Disconnect the client
@@ -38,7 +28,7 @@ fn test_client_connection() {
     // Stop the server and wait for thread to finish
     server.stop();
     assert!(
-        handle.join().is_ok(),
+        server.join().is_ok(),
         "Server thread panicked or failed to join"
     );
 }
@@ -47,15 +37,15 @@ fn test_client_connection() {
 fn test_client_echo_message() {
     // Set up the server in a separate thread
     let server = create_server();
-    let handle = setup_server_thread(server.clone());
     let server_port = server.get_port();
     // Create and connect the client
-    let mut client = client::Client::new("localhost", server_port.into(), 1000);
+    let mut client = Client::new("localhost", server_port.into(), 1000);
     assert!(client.connect().is_ok(), "Failed to connect to the server");
 
     // Prepare the message
-    let mut echo_message = EchoMessage::default();
-    echo_message.content = "Hello, World!".to_string();
+    let echo_message = EchoMessage {
+        content: "Hello, World!".to_string(),
+    };
     let message = client_message::Message::EchoMessage(echo_message.clone());
 
     // Send the message to the server
@@ -88,7 +78,7 @@ fn test_client_echo_message() {
     // Stop the server and wait for thread to finish
     server.stop();
     assert!(
-        handle.join().is_ok(),
+        server.join().is_ok(),
         "Server thread panicked or failed to join"
     );
 }
@@ -98,10 +88,9 @@ fn test_client_echo_message() {
 fn test_multiple_echo_messages() {
     // Set up the server in a separate thread
     let server = create_server();
-    let handle = setup_server_thread(server.clone());
     let server_port = server.get_port();
     // Create and connect the client
-    let mut client = client::Client::new("localhost", server_port.into(), 1000);
+    let mut client = Client::new("localhost", server_port.into(), 1000);
     assert!(client.connect().is_ok(), "Failed to connect to the server");
 
     // Prepare multiple messages
@@ -113,8 +102,9 @@ fn test_multiple_echo_messages() {
 
     // Send and receive multiple messages
     for message_content in messages {
-        let mut echo_message = EchoMessage::default();
-        echo_message.content = message_content.clone();
+        let echo_message = EchoMessage {
+            content: message_content.clone(),
+        };
         let message = client_message::Message::EchoMessage(echo_message);
 
         // Send the message to the server
@@ -148,7 +138,7 @@ fn test_multiple_echo_messages() {
     // Stop the server and wait for thread to finish
     server.stop();
     assert!(
-        handle.join().is_ok(),
+        server.join().is_ok(),
         "Server thread panicked or failed to join"
     );
 }
@@ -158,13 +148,12 @@ fn test_multiple_echo_messages() {
 fn test_multiple_clients() {
     // Set up the server in a separate thread
     let server = create_server();
-    let handle = setup_server_thread(server.clone());
     let server_port = server.get_port();
     // Create and connect multiple clients
-    let mut clients = vec![
-        client::Client::new("localhost", server_port.into(), 1000),
-        client::Client::new("localhost", server_port.into(), 1000),
-        client::Client::new("localhost", server_port.into(), 1000),
+    let mut clients = [
+        Client::new("localhost", server_port.into(), 1000),
+        Client::new("localhost", server_port.into(), 1000),
+        Client::new("localhost", server_port.into(), 1000),
     ];
 
     for client in clients.iter_mut() {
@@ -180,8 +169,9 @@ fn test_multiple_clients() {
 
     // Send and receive multiple messages for each client
     for message_content in messages {
-        let mut echo_message = EchoMessage::default();
-        echo_message.content = message_content.clone();
+        let echo_message = EchoMessage {
+            content: message_content.clone(),
+        };
         let message = client_message::Message::EchoMessage(echo_message.clone());
 
         for client in clients.iter_mut() {
@@ -221,7 +211,7 @@ fn test_multiple_clients() {
     // Stop the server and wait for thread to finish
     server.stop();
     assert!(
-        handle.join().is_ok(),
+        server.join().is_ok(),
         "Server thread panicked or failed to join"
     );
 }
@@ -231,18 +221,15 @@ fn test_multiple_clients() {
 fn test_client_add_request() {
     // Set up the server in a separate thread
     let server = create_server();
-    let handle = setup_server_thread(server.clone());
     let server_port = server.get_port();
     // Create and connect the client
-    let mut client = client::Client::new("localhost", server_port.into(), 1000);
+    let mut client = Client::new("localhost", server_port.into(), 1000);
     assert!(client.connect().is_ok(), "Failed to connect to the server");
 
     // Prepare the message
-    let mut add_request = AddRequest::default();
-    add_request.a = 10;
-    add_request.b = 20;
-    let message = client_message::Message::AddRequest(add_request.clone());
-    println!("elabbas a7a {:?}",message);
+    let add_request = AddRequest { a: 10, b: 20 };
+    let message = client_message::Message::AddRequest(add_request);
+    println!("elabbas a7a {:?}", message);
     // Send the message to the server
     assert!(client.send(message).is_ok(), "Failed to send message");
 
@@ -273,7 +260,101 @@ fn test_client_add_request() {
     // Stop the server and wait for thread to finish
     server.stop();
     assert!(
-        handle.join().is_ok(),
+        server.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_large_echo_message() {
+    // Set up the server in a separate thread
+    let server = create_server();
+    let server_port = server.get_port();
+    // Create and connect the client
+    let mut client = Client::new("localhost", server_port.into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+
+    // Well beyond the old 512-byte read buffer
+    let content = "0123456789abcdef".repeat(4096);
+    let message = client_message::Message::EchoMessage(EchoMessage {
+        content: content.clone(),
+    });
+    assert!(client.send(message).is_ok(), "Failed to send message");
+
+    match client
+        .receive()
+        .expect("Failed to receive response")
+        .message
+    {
+        Some(server_message::Message::EchoMessage(echo)) => {
+            assert_eq!(
+                echo.content, content,
+                "Echoed message content does not match"
+            );
+        }
+        _ => panic!("Expected EchoMessage, but received a different message"),
+    }
+
+    // Disconnect the client
+    assert!(
+        client.disconnect().is_ok(),
+        "Failed to disconnect from the server"
+    );
+
+    // Stop the server and wait for thread to finish
+    server.stop();
+    assert!(
+        server.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_pipelined_requests_are_correlated() {
+    // Set up the server in a separate thread
+    let server = create_server();
+    let server_port = server.get_port();
+    // Create and connect the client
+    let mut client = Client::new("localhost", server_port.into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+
+    // Send several requests before reading any response
+    let mut requests = Vec::new();
+    for a in 1..=3 {
+        let message = client_message::Message::AddRequest(AddRequest { a, b: 100 });
+        let request_id = client
+            .send_request(message)
+            .expect("Failed to send message");
+        requests.push((request_id, a + 100));
+    }
+
+    // Collect the responses in the opposite order they were sent
+    for (request_id, expected) in requests.into_iter().rev() {
+        let response = client
+            .receive_response(request_id)
+            .expect("Failed to receive response");
+        assert_eq!(response.request_id, request_id);
+        match response.message {
+            Some(server_message::Message::AddResponse(add_response)) => {
+                assert_eq!(
+                    add_response.result, expected,
+                    "Response matched to the wrong request"
+                );
+            }
+            _ => panic!("Expected AddResponse, but received a different message"),
+        }
+    }
+
+    // Disconnect the client
+    assert!(
+        client.disconnect().is_ok(),
+        "Failed to disconnect from the server"
+    );
+
+    // Stop the server and wait for thread to finish
+    server.stop();
+    assert!(
+        server.join().is_ok(),
         "Server thread panicked or failed to join"
     );
 }
@@ -0,0 +1,97 @@
+use embedded_recruitment_task::{
+    client::Client,
+    handler::{self, MessageKind},
+    message::{client_message, server_message, EchoMessage},
+    server::{Server, ServerBuilder},
+};
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// Echo handler that takes a while for requests asking to be slow
+fn sleepy_echo(request: client_message::Message) -> server_message::Message {
+    if let client_message::Message::EchoMessage(ref echo) = request {
+        if echo.content == "slow" {
+            thread::sleep(Duration::from_millis(300));
+        }
+    }
+    handler::echo(request)
+}
+
+fn setup_server(server: Server) -> (Arc<Server>, JoinHandle<()>) {
+    let server = Arc::new(server.with_handler(MessageKind::Echo, sleepy_echo));
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+    (server, handle)
+}
+
+fn echo(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+// Sends a slow and a fast request back to back and returns the request IDs
+// in the order their responses arrived
+fn response_order(server: &Server) -> Vec<u64> {
+    let mut client = Client::new("localhost", server.get_port().into(), 2000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+
+    let slow = client.send_request(echo("slow")).unwrap();
+    let fast = client.send_request(echo("fast")).unwrap();
+
+    let order = (0..2)
+        .map(|_| {
+            client
+                .receive()
+                .expect("Failed to receive response")
+                .request_id
+        })
+        .collect::<Vec<_>>();
+    assert!(order.contains(&slow) && order.contains(&fast));
+
+    assert!(client.disconnect().is_ok());
+    order
+}
+
+#[test]
+fn test_responses_are_sent_as_requests_complete() {
+    let (server, handle) = setup_server(
+        ServerBuilder::new("localhost:0")
+            .with_workers(2)
+            .build()
+            .unwrap(),
+    );
+
+    // The fast request overtakes the slow one sent before it
+    assert_eq!(response_order(&server), vec![2, 1]);
+
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_in_flight_limit_serializes_a_connection() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_workers(2)
+        .with_max_in_flight(1)
+        .build()
+        .unwrap();
+    let (server, handle) = setup_server(server);
+
+    // With one request in flight the fast one waits for the slow one
+    assert_eq!(response_order(&server), vec![1, 2]);
+
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
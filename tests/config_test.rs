@@ -0,0 +1,265 @@
+use embedded_recruitment_task::{
+    client::Client,
+    handler::{OverflowPolicy, Router},
+    server::{Backend, ConfigError, ServerBuilder, ServerConfig},
+};
+use std::{env, fs, time::Duration};
+
+#[test]
+fn test_config_from_toml() {
+    let config = ServerConfig::from_toml(
+        r#"
+        addr = "127.0.0.1:0"
+        backend = "event_loop"
+        workers = 3
+        keepalive = "90s"
+        shutdown_timeout = "250ms"
+        nodelay = true
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.addr, "127.0.0.1:0");
+    assert_eq!(config.backend, Backend::EventLoop);
+    assert_eq!(config.workers, 3);
+    assert_eq!(config.keepalive, Some(Duration::from_secs(90)));
+    assert_eq!(config.shutdown_timeout, Duration::from_millis(250));
+    assert!(config.nodelay);
+    // Keys left out keep their defaults
+    assert_eq!(config.backlog, ServerConfig::default().backlog);
+}
+
+#[test]
+fn test_config_errors_name_the_key() {
+    let error = ServerConfig::from_toml("wokers = 3").unwrap_err();
+    assert!(matches!(error, ConfigError::UnknownKey { ref key } if key == "wokers"));
+    assert_eq!(error.to_string(), "unknown configuration key wokers");
+
+    let error = ServerConfig::from_toml("workers = \"many\"").unwrap_err();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "workers"));
+
+    let error = ServerConfig::from_toml("read_timeout = \"10 parsecs\"").unwrap_err();
+    assert!(error.to_string().contains("read_timeout"));
+
+    let error = ServerConfig::from_toml("workers = ").unwrap_err();
+    assert!(matches!(error, ConfigError::Syntax(_)));
+}
+
+#[test]
+fn test_environment_overrides_file() {
+    let mut config = ServerConfig::from_toml("addr = \"127.0.0.1:1234\"\nworkers = 2").unwrap();
+    config
+        .apply_vars([
+            ("ERT_SERVER_ADDR", "127.0.0.1:0"),
+            ("ERT_SERVER_READ_TIMEOUT", "5s"),
+            ("UNRELATED", "ignored"),
+        ])
+        .unwrap();
+    assert_eq!(config.addr, "127.0.0.1:0");
+    assert_eq!(config.workers, 2);
+    assert_eq!(config.read_timeout, Some(Duration::from_secs(5)));
+
+    // Errors name the variable, not just the key
+    let error = config
+        .apply_vars([("ERT_SERVER_BACKLOG", "-1")])
+        .unwrap_err();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "ERT_SERVER_BACKLOG"));
+    let error = config.apply_vars([("ERT_SERVER_BOGUS", "1")]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unknown configuration key ERT_SERVER_BOGUS"
+    );
+}
+
+#[test]
+fn test_printed_config_round_trips() {
+    let mut config = ServerConfig::new("127.0.0.1:0");
+    config.read_timeout = Some(Duration::from_millis(1500));
+    config.log_payloads = true;
+    config.overflow_policy = OverflowPolicy::Saturate;
+
+    config.tls_cert = Some("certs/server.crt".into());
+    config.tls_key = Some("certs/server.key".into());
+    config.tls_client_ca = Some("certs/clients.pem".into());
+    config.auth_tokens = Some("/etc/ert/tokens".into());
+    config.request_rate = Some(100);
+    config.ip_byte_rate = Some(1 << 20);
+    config.max_connections_per_ip = Some(4);
+    config.allow = vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()];
+
+    let printed = config.to_string();
+    assert!(printed.contains("read_timeout = \"1500ms\""));
+    assert!(printed.contains("keepalive = \"off\""));
+    assert!(printed.contains("overflow_policy = \"saturate\""));
+    assert!(printed.contains("tls_cert = \"certs/server.crt\""));
+    assert!(printed.contains("request_rate = 100"));
+    assert!(printed.contains("byte_rate = \"off\""));
+    assert!(printed.contains("allow = [\"10.0.0.0/8\", \"::1/128\"]"));
+    assert!(printed.contains("deny = []"));
+    assert_eq!(ServerConfig::from_toml(&printed).unwrap(), config);
+}
+
+#[test]
+fn test_rate_limits_are_positive() {
+    let config = ServerConfig::from_toml("request_rate = 50\nip_byte_rate = \"off\"").unwrap();
+    assert_eq!(config.request_rate, Some(50));
+    assert_eq!(config.ip_byte_rate, None);
+
+    let mut config = ServerConfig::new("127.0.0.1:0");
+    config.set("rate_limit_strikes", "0").unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Invalid {
+            key: "rate_limit_strikes",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_networks_from_lists_and_environment() {
+    let mut config =
+        ServerConfig::from_toml("allow = [\"192.168.0.0/16\", \"10.1.2.3\"]\ndeny = []").unwrap();
+    assert_eq!(
+        config.allow,
+        [
+            "192.168.0.0/16".parse().unwrap(),
+            "10.1.2.3/32".parse().unwrap()
+        ]
+    );
+    assert!(config.deny.is_empty());
+
+    config
+        .apply_vars([("ERT_SERVER_DENY", "192.168.1.0/24, fe80::/10")])
+        .unwrap();
+    assert_eq!(config.deny.len(), 2);
+
+    let error = config.set("allow", "10.0.0.0/33").unwrap_err();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "allow"));
+    assert!(error.to_string().contains("10.0.0.0/33"));
+    let error = ServerConfig::from_toml("deny = [1, 2]").unwrap_err();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "deny"));
+
+    config.set("max_connections_per_ip", "0").unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Invalid {
+            key: "max_connections_per_ip",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_tls_settings_come_in_pairs() {
+    let mut config = ServerConfig::new("127.0.0.1:0");
+    config.set("tls_cert", "server.crt").unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Conflict {
+            key: "tls_cert",
+            other: "tls_key",
+            ..
+        })
+    ));
+
+    config.set("tls_key", "server.key").unwrap();
+    let result = config.validate();
+    if cfg!(feature = "tls") {
+        assert!(result.is_ok());
+    } else {
+        assert!(matches!(
+            result,
+            Err(ConfigError::Invalid {
+                key: "tls_cert",
+                ..
+            })
+        ));
+    }
+
+    config.set("tls_cert", "off").unwrap();
+    assert_eq!(config.tls_cert, None);
+
+    // Client certificates only make sense on top of TLS
+    config.set("tls_key", "off").unwrap();
+    config.set("tls_client_ca", "clients.pem").unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Conflict {
+            key: "tls_client_ca",
+            other: "tls_cert",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_overflow_policy_from_config() {
+    let mut config = ServerConfig::from_toml("overflow_policy = \"widen\"").unwrap();
+    assert_eq!(config.overflow_policy, OverflowPolicy::Widen);
+    config
+        .apply_vars([("ERT_SERVER_OVERFLOW_POLICY", "saturate")])
+        .unwrap();
+    assert_eq!(config.overflow_policy, OverflowPolicy::Saturate);
+
+    let error = config.set("overflow_policy", "wrap").unwrap_err();
+    assert!(matches!(
+        error,
+        ConfigError::Value { ref key, ref value, .. } if key == "overflow_policy" && value == "wrap"
+    ));
+    let error = config
+        .apply_vars([("ERT_SERVER_OVERFLOW_POLICY", "wrap")])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ConfigError::Value { ref key, .. } if key == "ERT_SERVER_OVERFLOW_POLICY"
+    ));
+
+    // The built server answers with the configured policy
+    config.addr = "localhost:0".to_string();
+    let server = ServerBuilder::from_config(config)
+        .build()
+        .unwrap()
+        .spawn()
+        .unwrap();
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().unwrap();
+    assert_eq!(client.add(i32::MAX, 1).unwrap(), i64::from(i32::MAX));
+}
+
+#[test]
+fn test_replaced_router_keeps_the_overflow_policy() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_overflow_policy(OverflowPolicy::Widen)
+        .with_router(Router::default())
+        .build()
+        .unwrap();
+    assert_eq!(server.config().overflow_policy, OverflowPolicy::Widen);
+    let server = server.spawn().unwrap();
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().unwrap();
+    assert_eq!(client.add(i32::MAX, 1).unwrap(), i64::from(i32::MAX) + 1);
+}
+
+#[test]
+fn test_build_server_from_file() {
+    let path = env::temp_dir().join(format!("ert-server-{}.toml", std::process::id()));
+    fs::write(&path, "addr = \"127.0.0.1:0\"\nmax_connections = 0\n").unwrap();
+
+    // The file parses, but validation rejects it when building
+    let config = ServerConfig::from_file(&path).unwrap();
+    let error = ServerBuilder::from_config(config).build().err().unwrap();
+    assert!(matches!(
+        error,
+        ConfigError::Invalid {
+            key: "max_connections",
+            ..
+        }
+    ));
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        ServerConfig::from_file(&path),
+        Err(ConfigError::Read { .. })
+    ));
+}
@@ -0,0 +1,119 @@
+use embedded_recruitment_task::{
+    client::Client,
+    message::{client_message, server_message, EchoMessage},
+    server::{MetricsSnapshot, Server, ServerBuilder},
+};
+use std::{
+    io::Read,
+    net::TcpStream,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+fn setup_server(server: Server) -> (Arc<Server>, JoinHandle<()>) {
+    let server = Arc::new(server);
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+    (server, handle)
+}
+
+fn connected_client(server: &Server) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    client
+}
+
+// Round-trips an echo, proving the connection is being served
+fn assert_served(client: &mut Client) {
+    let message = client_message::Message::EchoMessage(EchoMessage {
+        content: "ping".to_string(),
+    });
+    assert!(client.send(message).is_ok(), "Failed to send message");
+    match client
+        .receive()
+        .expect("Failed to receive response")
+        .message
+    {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "ping"),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+}
+
+fn wait_for_metrics(server: &Server, done: impl Fn(&MetricsSnapshot) -> bool) -> MetricsSnapshot {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let metrics = server.metrics();
+        if done(&metrics) || Instant::now() > deadline {
+            return metrics;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_connections_beyond_capacity_are_refused() {
+    let (server, handle) = setup_server(
+        ServerBuilder::new("localhost:0")
+            .with_max_connections(1)
+            .with_accept_queue(0)
+            .build()
+            .unwrap(),
+    );
+
+    let mut first = connected_client(&server);
+    assert_served(&mut first);
+
+    // The only connection thread is busy and there is no queue
+    let mut refused = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    refused
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut buffer = [0u8; 1];
+    assert!(matches!(refused.read(&mut buffer), Ok(0) | Err(_)));
+
+    let metrics = wait_for_metrics(&server, |m| m.connections_refused == 1);
+    assert_eq!(metrics.connections_refused, 1);
+    assert_eq!(metrics.connections_active, 1);
+    assert_eq!(metrics.connections_accepted, 1);
+
+    assert!(first.disconnect().is_ok());
+
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_connections_wait_in_accept_queue() {
+    let (server, handle) = setup_server(
+        ServerBuilder::new("localhost:0")
+            .with_max_connections(1)
+            .with_accept_queue(1)
+            .build()
+            .unwrap(),
+    );
+
+    let mut first = connected_client(&server);
+    assert_served(&mut first);
+
+    let mut second = connected_client(&server);
+    let metrics = wait_for_metrics(&server, |m| m.connections_queued == 1);
+    assert_eq!(metrics.connections_queued, 1);
+    assert_eq!(metrics.connections_refused, 0);
+
+    // The queued connection is served as soon as the first one closes
+    assert!(first.disconnect().is_ok());
+    assert_served(&mut second);
+    assert!(second.disconnect().is_ok());
+
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
@@ -0,0 +1,126 @@
+use embedded_recruitment_task::{
+    framing::{encode_frame, FrameDecoder, DEFAULT_MAX_FRAME_SIZE},
+    handler::Router,
+    message::{
+        client_message, server_message, AddRequest, ClientMessage, ErrorCode, ErrorResponse,
+        ServerMessage,
+    },
+    server::{Server, ServerBuilder},
+};
+use prost::Message;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+fn setup_server(server: Server) -> (Arc<Server>, JoinHandle<()>) {
+    let server = Arc::new(server);
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+    (server, handle)
+}
+
+fn connect(server: &Server) -> TcpStream {
+    let stream = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream
+}
+
+fn expect_error(stream: &mut TcpStream, decoder: &mut FrameDecoder) -> ErrorResponse {
+    let frame = decoder
+        .read_frame(stream)
+        .expect("Failed to read response")
+        .expect("Server closed the connection");
+    match ServerMessage::decode(frame.as_slice()).unwrap().message {
+        Some(server_message::Message::ErrorResponse(error)) => error,
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_undecodable_message_reports_decode_failure() {
+    let (server, handle) = setup_server(Server::new("localhost:0").unwrap());
+    let mut stream = connect(&server);
+    let mut decoder = FrameDecoder::default();
+
+    // A well-formed frame whose payload is not a valid ClientMessage
+    stream.write_all(&[3, 0xff, 0xff, 0xff]).unwrap();
+    let error = expect_error(&mut stream, &mut decoder);
+    assert_eq!(error.code(), ErrorCode::DecodeFailure);
+    assert!(!error.detail.is_empty());
+
+    // The connection stays usable after a bad message
+    stream.write_all(&[0]).unwrap();
+    let error = expect_error(&mut stream, &mut decoder);
+    assert_eq!(error.code(), ErrorCode::UnknownVariant);
+
+    drop(stream);
+    server.stop();
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_unhandled_message_reports_unknown_variant() {
+    let server = Server::new("localhost:0")
+        .unwrap()
+        .with_router(Router::empty());
+    let (server, handle) = setup_server(server);
+    let mut stream = connect(&server);
+
+    let request = ClientMessage {
+        message: Some(client_message::Message::AddRequest(AddRequest {
+            a: 1,
+            b: 2,
+        })),
+        request_id: 7,
+    };
+    stream
+        .write_all(&encode_frame(&request, DEFAULT_MAX_FRAME_SIZE).unwrap())
+        .unwrap();
+    let frame = FrameDecoder::default()
+        .read_frame(&mut stream)
+        .unwrap()
+        .unwrap();
+    let response = ServerMessage::decode(frame.as_slice()).unwrap();
+    // Errors carry the ID of the request they answer
+    assert_eq!(response.request_id, 7);
+    match response.message {
+        Some(server_message::Message::ErrorResponse(error)) => {
+            assert_eq!(error.code(), ErrorCode::UnknownVariant)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+
+    drop(stream);
+    server.stop();
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_oversize_frame_is_reported_before_disconnect() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_max_frame_size(64)
+        .build()
+        .unwrap();
+    let (server, handle) = setup_server(server);
+    let mut stream = connect(&server);
+
+    // Only the length prefix is needed for the server to reject the frame
+    stream.write_all(&[0x80, 0x08]).unwrap();
+    let error = expect_error(&mut stream, &mut FrameDecoder::default());
+    assert_eq!(error.code(), ErrorCode::DecodeFailure);
+
+    // ...after which the server hangs up
+    let mut buffer = [0u8; 16];
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+
+    server.stop();
+    assert!(handle.join().is_ok());
+}
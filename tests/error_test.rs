@@ -0,0 +1,137 @@
+use embedded_recruitment_task::{
+    client::Client,
+    framing::FrameError,
+    server::{ConfigError, Server, ServerBuilder},
+    Error,
+};
+use std::{
+    error::Error as _,
+    io::{Read, Write},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// Accepts one connection and hands it to `serve`
+fn fake_server<F>(serve: F) -> u16
+where
+    F: FnOnce(std::net::TcpStream) + Send + 'static,
+{
+    let listener = TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream);
+    });
+    port
+}
+
+#[test]
+fn test_oversized_frames_are_reported_with_sizes() {
+    let server = Server::new("localhost:0").and_then(Server::spawn).unwrap();
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_max_frame_size(16);
+    client.connect().unwrap();
+
+    match client.echo(&"x".repeat(64)) {
+        Err(Error::FrameTooLarge { len, max }) => {
+            assert!(len > 64);
+            assert_eq!(max, 16);
+        }
+        other => panic!("Expected FrameTooLarge, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_missing_responses_time_out() {
+    let port = fake_server(|mut stream| {
+        // Read the request but never answer it
+        let mut buffer = [0u8; 64];
+        let _ = stream.read(&mut buffer);
+        thread::sleep(Duration::from_millis(500));
+    });
+    let mut client = Client::new("localhost", port.into(), 100);
+    client.connect().unwrap();
+
+    let error = client.echo("anyone?").unwrap_err();
+    assert!(matches!(error, Error::Timeout), "{:?}", error);
+    assert!(error.is_timeout());
+}
+
+#[test]
+fn test_undecodable_responses_keep_the_decode_error() {
+    let port = fake_server(|mut stream| {
+        let mut buffer = [0u8; 64];
+        let _ = stream.read(&mut buffer);
+        // A well-formed frame whose payload is not a ServerMessage
+        stream.write_all(&[3, 0xff, 0xff, 0xff]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+    let mut client = Client::new("localhost", port.into(), 1000);
+    client.connect().unwrap();
+
+    let error = client.echo("hi").unwrap_err();
+    assert!(matches!(error, Error::Decode(_)), "{:?}", error);
+    let source = error.source().expect("decode errors have a source");
+    assert!(source.downcast_ref::<prost::DecodeError>().is_some());
+}
+
+#[test]
+fn test_truncated_frames_are_frame_errors() {
+    let port = fake_server(|mut stream| {
+        let mut buffer = [0u8; 64];
+        let _ = stream.read(&mut buffer);
+        // Promise ten bytes, deliver two, then hang up
+        stream.write_all(&[10, 1, 2]).unwrap();
+    });
+    let mut client = Client::new("localhost", port.into(), 1000);
+    client.connect().unwrap();
+
+    match client.echo("hi") {
+        // The count includes the one-byte length prefix
+        Err(Error::Frame(FrameError::Truncated { buffered })) => assert_eq!(buffered, 3),
+        other => panic!("Expected a truncated frame, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_running_a_stopped_server_fails() {
+    let server = Arc::new(Server::new("localhost:0").unwrap());
+    let runner = {
+        let server = Arc::clone(&server);
+        thread::spawn(move || server.run())
+    };
+    thread::sleep(Duration::from_millis(50));
+    server.stop();
+    assert!(runner.join().unwrap().is_ok());
+
+    assert!(matches!(server.run(), Err(Error::Shutdown)));
+}
+
+#[test]
+fn test_errors_chain_to_their_cause() {
+    let config_error = ServerBuilder::new("localhost:0")
+        .with_workers(0)
+        .build()
+        .err()
+        .unwrap();
+    let error = Error::from(config_error);
+    assert!(matches!(error, Error::Config(ConfigError::Invalid { .. })));
+    assert!(error
+        .source()
+        .and_then(|source| source.downcast_ref::<ConfigError>())
+        .is_some());
+
+    // Bind failures surface as plain I/O errors
+    let taken = Server::new("localhost:0").unwrap();
+    let error = Server::new(&format!("127.0.0.1:{}", taken.get_port()))
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::Io(_)), "{:?}", error);
+    assert!(error.source().is_some());
+
+    let error = Error::from(FrameError::TooLarge { len: 10, max: 5 });
+    assert!(matches!(error, Error::FrameTooLarge { len: 10, max: 5 }));
+    assert!(error.to_string().contains("10 bytes"));
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "cli")]
+
+use embedded_recruitment_task::server::{Server, ServerHandle};
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn create_server() -> ServerHandle {
+    Server::new("localhost:0")
+        .and_then(Server::spawn)
+        .expect("Failed to start server")
+}
+
+fn ert_client(server: &ServerHandle) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ert-client"));
+    command.args(["--addr", &server.local_addr().to_string()]);
+    command
+}
+
+fn run_with_input(mut command: Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_single_requests_print_text() {
+    let server = create_server();
+
+    let output = ert_client(&server)
+        .args(["echo", "hello", "world"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+
+    let output = ert_client(&server)
+        .args(["add", "--", "-2", "44"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn test_error_response_sets_exit_code() {
+    let server = create_server();
+
+    let output = ert_client(&server)
+        .args(["--format", "json", "add", "2147483647", "1"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let printed = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        printed.contains("\"code\":\"ERROR_CODE_OVERFLOW\""),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn test_script_from_stdin_prints_json() {
+    let server = create_server();
+
+    let mut command = ert_client(&server);
+    command.args(["--format", "json", "script", "-"]);
+    let output = run_with_input(command, "# greeting\necho hi\n\nadd 1 2\n");
+    assert!(output.status.success());
+
+    let lines: Vec<_> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"echo":"hi","request_id":1}"#,
+            r#"{"add":{"result":3,"wide_result":0},"request_id":2}"#,
+        ]
+    );
+}
+
+#[test]
+fn test_bad_script_line_is_reported() {
+    let server = create_server();
+
+    let mut command = ert_client(&server);
+    command.args(["script", "-"]);
+    let output = run_with_input(command, "echo ok\nmultiply 2 3\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2"));
+}
+
+#[test]
+fn test_unreachable_server_exits_with_unavailable() {
+    let server = create_server();
+    let addr = server.local_addr().to_string();
+    drop(server);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ert-client"))
+        .args(["--addr", &addr, "echo", "anyone"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(69));
+}
@@ -0,0 +1,91 @@
+#![cfg(all(unix, feature = "cli"))]
+
+use std::{
+    net::{TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn ert_server() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ert-server"));
+    command.env_remove("RUST_LOG").stderr(Stdio::null());
+    command
+}
+
+fn wait_for_listener(addr: &str) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect(addr).is_err() {
+        assert!(Instant::now() < deadline, "Server never started listening");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn wait_with_timeout(child: &mut Child) -> Option<i32> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.code();
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("Server did not exit");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_print_config_layers_flags_over_environment() {
+    let output = ert_server()
+        .args(["--workers", "3", "--print-config"])
+        .env("ERT_SERVER_ADDR", "127.0.0.1:9999")
+        .env("ERT_SERVER_WORKERS", "7")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert!(printed.contains("addr = \"127.0.0.1:9999\""));
+    assert!(printed.contains("workers = 3"));
+}
+
+#[test]
+fn test_invalid_config_exits_with_config_error() {
+    let status = ert_server().args(["--workers", "0"]).status().unwrap();
+    assert_eq!(status.code(), Some(78));
+}
+
+#[test]
+fn test_bind_failure_exits_with_unavailable() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = taken.local_addr().unwrap().to_string();
+    let status = ert_server().args(["--addr", &addr]).status().unwrap();
+    assert_eq!(status.code(), Some(69));
+}
+
+#[test]
+fn test_sigterm_stops_the_server() {
+    let addr = format!("127.0.0.1:{}", free_port());
+    let mut child = ert_server()
+        .args(["--addr", &addr])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_listener(&addr);
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert_eq!(wait_with_timeout(&mut child), Some(0));
+}
@@ -0,0 +1,206 @@
+use embedded_recruitment_task::{
+    client::Client,
+    framing::{encode_frame, FrameDecoder, DEFAULT_MAX_FRAME_SIZE},
+    handler::{self, MessageKind},
+    message::{
+        client_message, server_message, AddRequest, ClientMessage, EchoMessage, ServerMessage,
+    },
+    server::{Backend, Server, ServerBuilder},
+};
+use prost::Message;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+fn setup_server(builder: ServerBuilder) -> (Arc<Server>, JoinHandle<()>) {
+    let server = builder
+        .with_backend(Backend::EventLoop)
+        .build()
+        .expect("Failed to build the server");
+    let server = Arc::new(server);
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+    (server, handle)
+}
+
+fn stop_server(server: Arc<Server>, handle: JoinHandle<()>) {
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+fn echo(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+#[test]
+fn test_event_loop_serves_many_clients() {
+    let (server, handle) = setup_server(ServerBuilder::new("localhost:0"));
+
+    let mut clients: Vec<_> = (0..8)
+        .map(|_| {
+            let mut client = Client::new("localhost", server.get_port().into(), 1000);
+            assert!(client.connect().is_ok(), "Failed to connect to the server");
+            client
+        })
+        .collect();
+
+    for (i, client) in clients.iter_mut().enumerate() {
+        let a = i as i32;
+        let request = client_message::Message::AddRequest(AddRequest { a, b: 1 });
+        assert!(client.send(request).is_ok(), "Failed to send message");
+    }
+    for (i, client) in clients.iter_mut().enumerate() {
+        match client
+            .receive()
+            .expect("Failed to receive response")
+            .message
+        {
+            Some(server_message::Message::AddResponse(add)) => assert_eq!(add.result, i as i32 + 1),
+            other => panic!("Expected AddResponse, got {:?}", other),
+        }
+    }
+
+    for client in clients.iter_mut() {
+        assert!(client.disconnect().is_ok());
+    }
+    stop_server(server, handle);
+}
+
+#[test]
+fn test_event_loop_handles_large_and_split_messages() {
+    let (server, handle) = setup_server(ServerBuilder::new("localhost:0"));
+    let mut stream = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    stream.set_nodelay(true).unwrap();
+
+    // Dribble a large frame out in small pieces
+    let content = "event loop ".repeat(10_000);
+    let request = ClientMessage {
+        message: Some(echo(&content)),
+        request_id: 9,
+    };
+    let frame = encode_frame(&request, DEFAULT_MAX_FRAME_SIZE).unwrap();
+    for piece in frame.chunks(frame.len() / 5 + 1) {
+        stream.write_all(piece).unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let payload = FrameDecoder::default()
+        .read_frame(&mut stream)
+        .unwrap()
+        .unwrap();
+    let response = ServerMessage::decode(payload.as_slice()).unwrap();
+    assert_eq!(response.request_id, 9);
+    match response.message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, content),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+
+    drop(stream);
+    stop_server(server, handle);
+}
+
+#[test]
+fn test_event_loop_answers_out_of_order() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_workers(2)
+        .with_handler(MessageKind::Echo, |request| {
+            if let client_message::Message::EchoMessage(ref echo) = request {
+                if echo.content == "slow" {
+                    thread::sleep(Duration::from_millis(300));
+                }
+            }
+            handler::echo(request)
+        });
+    let (server, handle) = setup_server(server);
+
+    let mut client = Client::new("localhost", server.get_port().into(), 2000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    let slow = client.send_request(echo("slow")).unwrap();
+    let fast = client.send_request(echo("fast")).unwrap();
+
+    assert_eq!(client.receive().unwrap().request_id, fast);
+    assert_eq!(client.receive().unwrap().request_id, slow);
+
+    assert!(client.disconnect().is_ok());
+    stop_server(server, handle);
+}
+
+#[test]
+fn test_event_loop_refuses_beyond_max_connections() {
+    let (server, handle) = setup_server(
+        ServerBuilder::new("localhost:0")
+            .with_max_connections(1)
+            .with_accept_queue(0),
+    );
+
+    let mut first = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(first.connect().is_ok(), "Failed to connect to the server");
+    assert!(first.send(echo("ping")).is_ok());
+    assert!(first.receive().is_ok());
+
+    let mut refused = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    refused
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut buffer = [0u8; 1];
+    assert!(matches!(refused.read(&mut buffer), Ok(0) | Err(_)));
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while server.metrics().connections_refused == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(server.metrics().connections_refused, 1);
+    assert_eq!(server.metrics().connections_active, 1);
+
+    assert!(first.disconnect().is_ok());
+    stop_server(server, handle);
+}
+
+#[test]
+fn test_event_loop_stops_reading_from_clients_that_never_read() {
+    let (server, handle) =
+        setup_server(ServerBuilder::new("localhost:0").with_max_frame_size(1024));
+
+    // Every empty frame is answered with an error the client never reads
+    let mut flood = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    flood
+        .set_write_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let chunk = [0u8; 64 * 1024];
+    let mut sent = 0;
+    while sent < 32 * 1024 * 1024 {
+        match flood.write(&chunk) {
+            Ok(n) => sent += n,
+            Err(_) => break,
+        }
+    }
+    assert!(
+        sent < 32 * 1024 * 1024,
+        "The server kept reading {} bytes without its answers being read",
+        sent
+    );
+
+    // Other clients are still served
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    assert!(client.send(echo("ping")).is_ok());
+    assert!(client.receive().is_ok());
+
+    drop(flood);
+    assert!(client.disconnect().is_ok());
+    stop_server(server, handle);
+}
@@ -0,0 +1,132 @@
+use embedded_recruitment_task::{
+    framing::{encode_frame, FrameDecoder, FrameError, DEFAULT_MAX_FRAME_SIZE},
+    message::{client_message, server_message, ClientMessage, EchoMessage, ServerMessage},
+    server::Server,
+};
+use prost::Message;
+use std::{io::Write, net::TcpStream, sync::Arc, thread, time::Duration};
+
+fn echo(content: &str) -> ClientMessage {
+    ClientMessage {
+        message: Some(client_message::Message::EchoMessage(EchoMessage {
+            content: content.to_string(),
+        })),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_decoder_handles_split_frames() {
+    let frame = encode_frame(&echo("split across reads"), DEFAULT_MAX_FRAME_SIZE).unwrap();
+    let mut decoder = FrameDecoder::default();
+
+    // Feed the frame one byte at a time
+    for (i, byte) in frame.iter().enumerate() {
+        assert!(
+            decoder.decode().unwrap().is_none(),
+            "frame complete after {} bytes",
+            i
+        );
+        decoder.extend(&[*byte]);
+    }
+
+    let payload = decoder.decode().unwrap().expect("frame should be complete");
+    assert_eq!(
+        ClientMessage::decode(payload.as_slice()).unwrap(),
+        echo("split across reads")
+    );
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn test_decoder_handles_coalesced_frames() {
+    let mut bytes = encode_frame(&echo("first"), DEFAULT_MAX_FRAME_SIZE).unwrap();
+    bytes.extend(encode_frame(&echo("second"), DEFAULT_MAX_FRAME_SIZE).unwrap());
+
+    let mut decoder = FrameDecoder::default();
+    decoder.extend(&bytes);
+
+    let first = decoder.decode().unwrap().unwrap();
+    let second = decoder.decode().unwrap().unwrap();
+    assert_eq!(
+        ClientMessage::decode(first.as_slice()).unwrap(),
+        echo("first")
+    );
+    assert_eq!(
+        ClientMessage::decode(second.as_slice()).unwrap(),
+        echo("second")
+    );
+    assert!(decoder.decode().unwrap().is_none());
+}
+
+#[test]
+fn test_oversize_frames_are_rejected() {
+    let message = echo(&"x".repeat(64));
+    assert!(matches!(
+        encode_frame(&message, 16),
+        Err(FrameError::TooLarge { max: 16, .. })
+    ));
+
+    // The decoder rejects the frame from its length prefix alone
+    let frame = encode_frame(&message, DEFAULT_MAX_FRAME_SIZE).unwrap();
+    let mut decoder = FrameDecoder::new(16);
+    decoder.extend(&frame[..2]);
+    assert!(matches!(
+        decoder.decode(),
+        Err(FrameError::TooLarge { max: 16, .. })
+    ));
+}
+
+#[test]
+fn test_truncated_frames_are_reported() {
+    let frame = encode_frame(&echo("cut short"), DEFAULT_MAX_FRAME_SIZE).unwrap();
+    let mut reader = &frame[..frame.len() - 3];
+
+    let mut decoder = FrameDecoder::default();
+    assert!(matches!(
+        decoder.read_frame(&mut reader),
+        Err(FrameError::Truncated { .. })
+    ));
+
+    // A stream that ends between frames is a clean close
+    let mut empty: &[u8] = &[];
+    assert!(FrameDecoder::default()
+        .read_frame(&mut empty)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_server_handles_coalesced_frames() {
+    let server = Arc::new(Server::new("localhost:0").expect("Failed to start server"));
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+
+    let mut stream = TcpStream::connect(("localhost", server.get_port())).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    // Send two requests in a single write so they share a TCP segment
+    let mut bytes = encode_frame(&echo("one"), DEFAULT_MAX_FRAME_SIZE).unwrap();
+    bytes.extend(encode_frame(&echo("two"), DEFAULT_MAX_FRAME_SIZE).unwrap());
+    stream.write_all(&bytes).unwrap();
+
+    let mut decoder = FrameDecoder::default();
+    for expected in ["one", "two"] {
+        let frame = decoder.read_frame(&mut stream).unwrap().unwrap();
+        match ServerMessage::decode(frame.as_slice()).unwrap().message {
+            Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, expected),
+            other => panic!("Expected EchoMessage, got {:?}", other),
+        }
+    }
+
+    drop(stream);
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
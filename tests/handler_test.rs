@@ -0,0 +1,287 @@
+use embedded_recruitment_task::{
+    client::Client,
+    handler::{
+        with_context, AddHandler, ConnectionContext, Handler, MessageKind, OverflowPolicy,
+        PeerIdentity, Router,
+    },
+    message::{client_message, server_message, AddRequest, AddResponse, EchoMessage, ErrorCode},
+    server::Server,
+};
+use std::{net::SocketAddr, sync::Arc, thread};
+
+fn echo_request(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+#[test]
+fn test_default_router_serves_builtin_messages() {
+    let router = Router::default();
+
+    assert_eq!(
+        router.dispatch(echo_request("ping")),
+        server_message::Message::EchoMessage(EchoMessage {
+            content: "ping".to_string()
+        })
+    );
+    assert_eq!(
+        router.dispatch(client_message::Message::AddRequest(AddRequest {
+            a: 2,
+            b: 3
+        })),
+        server_message::Message::AddResponse(AddResponse {
+            result: 5,
+            wide_result: 0
+        })
+    );
+}
+
+#[test]
+fn test_empty_router_has_no_handlers() {
+    let router = Router::empty();
+    assert!(!router.handles(MessageKind::Echo));
+    match router.dispatch(echo_request("ping")) {
+        server_message::Message::ErrorResponse(error) => {
+            assert_eq!(error.code(), ErrorCode::UnknownVariant)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_panicking_handler_reports_internal_error() {
+    let router = Router::default().route(MessageKind::Echo, |_| panic!("handler bug"));
+
+    match router.dispatch(echo_request("ping")) {
+        server_message::Message::ErrorResponse(error) => {
+            assert_eq!(error.code(), ErrorCode::Internal)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+}
+
+/// Echoes who sent the request instead of its content
+fn whoami() -> impl Handler {
+    with_context(|context: &ConnectionContext, _| {
+        server_message::Message::EchoMessage(EchoMessage {
+            content: context.to_string(),
+        })
+    })
+}
+
+fn echoed(response: server_message::Message) -> String {
+    match response {
+        server_message::Message::EchoMessage(echo) => echo.content,
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_context_handler_sees_the_connection() {
+    let router = Router::empty().route(MessageKind::Echo, whoami());
+
+    // Outside a connection the context is empty
+    assert_eq!(
+        echoed(router.dispatch(echo_request("ping"))),
+        "unknown peer"
+    );
+
+    let addr: SocketAddr = "192.0.2.7:4000".parse().unwrap();
+    let context = ConnectionContext::new(addr);
+    assert_eq!(
+        echoed(router.dispatch_in(&context, echo_request("ping"))),
+        "192.0.2.7:4000"
+    );
+
+    let context = context.with_identity(PeerIdentity {
+        subject: "CN=device-7, O=Fleet".to_string(),
+        common_name: Some("device-7".to_string()),
+        sans: vec![],
+    });
+    assert_eq!(
+        echoed(router.dispatch_in(&context, echo_request("ping"))),
+        "device-7 (192.0.2.7:4000)"
+    );
+
+    // Plain handlers ignore the context
+    let router = Router::default();
+    assert_eq!(
+        echoed(router.dispatch_in(&context, echo_request("ping"))),
+        "ping"
+    );
+}
+
+#[test]
+fn test_custom_handler_replaces_builtin() {
+    let router = Router::default().route(MessageKind::Echo, |request| match request {
+        client_message::Message::EchoMessage(echo) => {
+            server_message::Message::EchoMessage(EchoMessage {
+                content: echo.content.to_uppercase(),
+            })
+        }
+        _ => unreachable!(),
+    });
+
+    assert_eq!(
+        router.dispatch(echo_request("shout")),
+        server_message::Message::EchoMessage(EchoMessage {
+            content: "SHOUT".to_string()
+        })
+    );
+    // Other kinds keep their built-in handler
+    assert!(router.handles(MessageKind::Add));
+}
+
+fn add_overflowing(policy: OverflowPolicy) -> server_message::Message {
+    AddHandler::new(policy).handle(client_message::Message::AddRequest(AddRequest {
+        a: i32::MAX,
+        b: 1,
+    }))
+}
+
+#[test]
+fn test_add_overflow_error_policy() {
+    match add_overflowing(OverflowPolicy::Error) {
+        server_message::Message::ErrorResponse(error) => {
+            assert_eq!(error.code(), ErrorCode::Overflow)
+        }
+        other => panic!("Expected ErrorResponse, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_add_overflow_saturate_policy() {
+    assert_eq!(
+        add_overflowing(OverflowPolicy::Saturate),
+        server_message::Message::AddResponse(AddResponse {
+            result: i32::MAX,
+            wide_result: 0
+        })
+    );
+}
+
+#[test]
+fn test_add_overflow_widen_policy() {
+    assert_eq!(
+        add_overflowing(OverflowPolicy::Widen),
+        server_message::Message::AddResponse(AddResponse {
+            result: i32::MAX,
+            wide_result: i64::from(i32::MAX) + 1
+        })
+    );
+
+    // In range sums are reported in both fields
+    let response = AddHandler::new(OverflowPolicy::Widen).handle(
+        client_message::Message::AddRequest(AddRequest { a: -7, b: 3 }),
+    );
+    assert_eq!(
+        response,
+        server_message::Message::AddResponse(AddResponse {
+            result: -4,
+            wide_result: -4
+        })
+    );
+}
+
+#[test]
+fn test_server_applies_overflow_policy() {
+    let server = Arc::new(
+        Server::new("localhost:0")
+            .expect("Failed to start server")
+            .with_overflow_policy(OverflowPolicy::Saturate),
+    );
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+
+    let request = client_message::Message::AddRequest(AddRequest { a: i32::MIN, b: -1 });
+    assert!(client.send(request).is_ok(), "Failed to send message");
+    match client
+        .receive()
+        .expect("Failed to receive response")
+        .message
+    {
+        Some(server_message::Message::AddResponse(add)) => assert_eq!(add.result, i32::MIN),
+        other => panic!("Expected AddResponse, got {:?}", other),
+    }
+
+    assert!(client.disconnect().is_ok());
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_server_uses_registered_handler() {
+    let server = Arc::new(
+        Server::new("localhost:0")
+            .expect("Failed to start server")
+            .with_handler(MessageKind::Add, |_| {
+                server_message::Message::AddResponse(AddResponse {
+                    result: 42,
+                    ..Default::default()
+                })
+            }),
+    );
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+
+    let request = client_message::Message::AddRequest(AddRequest { a: 1, b: 1 });
+    assert!(client.send(request).is_ok(), "Failed to send message");
+    match client
+        .receive()
+        .expect("Failed to receive response")
+        .message
+    {
+        Some(server_message::Message::AddResponse(add)) => assert_eq!(add.result, 42),
+        other => panic!("Expected AddResponse, got {:?}", other),
+    }
+
+    assert!(client.disconnect().is_ok());
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_server_passes_the_connection_to_handlers() {
+    let server = Arc::new(
+        Server::new("localhost:0")
+            .expect("Failed to start server")
+            .with_handler(MessageKind::Echo, whoami()),
+    );
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    let peer: SocketAddr = client
+        .echo("who am I?")
+        .expect("Failed to echo")
+        .parse()
+        .expect("Expected the peer address");
+    assert!(peer.ip().is_loopback());
+
+    assert!(client.disconnect().is_ok());
+    server.stop();
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
@@ -0,0 +1,73 @@
+use embedded_recruitment_task::{
+    client::Client,
+    handler::OverflowPolicy,
+    message::ErrorCode,
+    server::{Server, ServerHandle},
+    Error,
+};
+
+fn create_server(server: Server) -> ServerHandle {
+    server.spawn().expect("Failed to start server")
+}
+
+fn connect(server: &ServerHandle) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().expect("Failed to connect to the server");
+    client
+}
+
+#[test]
+fn test_typed_requests() {
+    let server = create_server(Server::new("localhost:0").unwrap());
+    let mut client = connect(&server);
+
+    assert_eq!(client.echo("Hello, World!").unwrap(), "Hello, World!");
+    assert_eq!(client.echo("").unwrap(), "");
+    assert_eq!(client.add(-10, 52).unwrap(), 42);
+    assert!(client.disconnect().is_ok());
+}
+
+#[test]
+fn test_error_response_becomes_server_error() {
+    let server = create_server(Server::new("localhost:0").unwrap());
+    let mut client = connect(&server);
+
+    match client.add(i32::MAX, 1) {
+        Err(Error::Server { code, detail }) => {
+            assert_eq!(code, ErrorCode::Overflow);
+            assert!(!detail.is_empty());
+        }
+        other => panic!("Expected a server error, got {:?}", other),
+    }
+    // The connection stays usable
+    assert_eq!(client.add(1, 1).unwrap(), 2);
+}
+
+#[test]
+fn test_add_reports_widened_sums() {
+    let server = Server::new("localhost:0")
+        .unwrap()
+        .with_overflow_policy(OverflowPolicy::Widen);
+    let server = create_server(server);
+    let mut client = connect(&server);
+
+    assert_eq!(client.add(i32::MAX, 1).unwrap(), i64::from(i32::MAX) + 1);
+    assert_eq!(client.add(-1, 1).unwrap(), 0);
+}
+
+#[test]
+fn test_connection_errors() {
+    let mut client = Client::new("localhost", 1, 1000);
+    assert!(matches!(client.echo("hi"), Err(Error::NotConnected)));
+
+    let server = create_server(Server::new("localhost:0").unwrap());
+    let mut client = connect(&server);
+    assert!(client.is_connected());
+    // Make sure the server has accepted the connection before stopping
+    assert!(client.echo("ping").is_ok());
+    server.stop();
+
+    // The drained server hangs up on the idle client
+    assert!(matches!(client.receive(), Err(Error::Disconnected)));
+    assert!(!client.is_connected());
+}
@@ -0,0 +1,138 @@
+use embedded_recruitment_task::{
+    client::Client,
+    framing::FrameDecoder,
+    message::{server_message, ErrorCode, ServerMessage},
+    server::{Backend, ServerBuilder, ServerHandle},
+    Error,
+};
+use prost::Message;
+use std::{io::Write, net::TcpStream, thread, time::Duration};
+
+fn create_server(builder: ServerBuilder) -> ServerHandle {
+    builder
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+fn connect(server: &ServerHandle) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().expect("Failed to connect to the server");
+    client
+}
+
+fn assert_rate_limited<T: std::fmt::Debug>(result: Result<T, Error>, expected: &str) {
+    match result {
+        Err(Error::Server { code, detail }) => {
+            assert_eq!(code, ErrorCode::RateLimited);
+            assert!(detail.contains(expected), "unexpected detail {:?}", detail);
+        }
+        other => panic!("Expected a rate limited error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_requests_per_second_per_connection() {
+    for backend in [Backend::Threaded, Backend::EventLoop] {
+        let server = create_server(
+            ServerBuilder::new("localhost:0")
+                .with_backend(backend)
+                .with_rate_limit(Some(3), None),
+        );
+        let mut client = connect(&server);
+
+        for _ in 0..3 {
+            assert_eq!(client.echo("hello").unwrap(), "hello");
+        }
+        assert_rate_limited(client.echo("hello"), "too many requests per second");
+
+        // Other connections have their own budget
+        assert_eq!(connect(&server).add(1, 2).unwrap(), 3);
+
+        // And the bucket refills over time
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(client.echo("again").unwrap(), "again");
+    }
+}
+
+#[test]
+fn test_bytes_per_second_per_connection() {
+    let server = create_server(ServerBuilder::new("localhost:0").with_rate_limit(None, Some(100)));
+    let mut client = connect(&server);
+
+    // A request larger than the whole budget passes once, leaving a debt
+    let large = "x".repeat(150);
+    assert_eq!(client.echo(&large).unwrap(), large);
+    assert_rate_limited(client.echo("small"), "too many bytes per second");
+}
+
+#[test]
+fn test_ip_limits_are_shared_between_connections() {
+    let server = create_server(ServerBuilder::new("localhost:0").with_ip_rate_limit(Some(2), None));
+    let mut first = connect(&server);
+    let mut second = connect(&server);
+
+    assert_eq!(first.echo("one").unwrap(), "one");
+    assert_eq!(second.echo("two").unwrap(), "two");
+    assert_rate_limited(first.echo("three"), "from 127.0.0.1");
+    assert_rate_limited(second.echo("three"), "from 127.0.0.1");
+}
+
+#[test]
+fn test_repeated_violations_disconnect() {
+    for backend in [Backend::Threaded, Backend::EventLoop] {
+        let server = create_server(
+            ServerBuilder::new("localhost:0")
+                .with_backend(backend)
+                .with_rate_limit(Some(1), None)
+                .with_rate_limit_strikes(2),
+        );
+        let mut client = connect(&server);
+
+        assert_eq!(client.echo("hello").unwrap(), "hello");
+        assert_rate_limited(client.echo("hello"), "too many requests");
+        // The second strike is still answered before the server hangs up
+        assert_rate_limited(client.echo("hello"), "too many requests");
+        assert!(client.echo("hello").is_err());
+    }
+}
+
+#[test]
+fn test_undecodable_frames_are_rate_limited() {
+    for backend in [Backend::Threaded, Backend::EventLoop] {
+        let server = create_server(
+            ServerBuilder::new("localhost:0")
+                .with_backend(backend)
+                .with_rate_limit(Some(2), None)
+                .with_rate_limit_strikes(2),
+        );
+        let mut stream = TcpStream::connect(("localhost", server.get_port())).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        // Well-formed frames whose payload is not a valid ClientMessage
+        for _ in 0..4 {
+            stream.write_all(&[3, 0xff, 0xff, 0xff]).unwrap();
+        }
+        let mut decoder = FrameDecoder::default();
+        let mut codes = Vec::new();
+        while let Some(frame) = decoder.read_frame(&mut stream).unwrap() {
+            match ServerMessage::decode(frame.as_slice()).unwrap().message {
+                Some(server_message::Message::ErrorResponse(error)) => codes.push(error.code()),
+                other => panic!("Expected ErrorResponse, got {:?}", other),
+            }
+        }
+        // The second refusal in a row closed the connection
+        assert_eq!(
+            codes,
+            [
+                ErrorCode::DecodeFailure,
+                ErrorCode::DecodeFailure,
+                ErrorCode::RateLimited,
+                ErrorCode::RateLimited
+            ]
+        );
+    }
+}
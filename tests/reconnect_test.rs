@@ -0,0 +1,284 @@
+use embedded_recruitment_task::{
+    client::{Client, ConnectionState, ReconnectPolicy, Replay},
+    message::{client_message, server_message, AddRequest, AuthRequest, EchoMessage},
+    server::{AuthError, Server, ServerBuilder, ServerHandle},
+    Error,
+};
+use socket2::SockRef;
+use std::{
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+fn create_server(addr: &str) -> ServerHandle {
+    Server::new(addr)
+        .and_then(Server::spawn)
+        .expect("Failed to start server")
+}
+
+fn fast_policy(max_attempts: u32, replay: Replay) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(100),
+        jitter: 0.0,
+        max_attempts,
+        replay,
+    }
+}
+
+/// Client that records every state change it reports
+fn recording_client(
+    port: u16,
+    policy: ReconnectPolicy,
+) -> (Client, Arc<Mutex<Vec<ConnectionState>>>) {
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let recorder = Arc::clone(&states);
+    let client = Client::new("localhost", port.into(), 1000)
+        .with_reconnect(policy)
+        .with_state_listener(move |state| recorder.lock().unwrap().push(state));
+    (client, states)
+}
+
+fn echo(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+#[test]
+fn test_backoff_grows_and_caps() {
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+        jitter: 0.0,
+        ..ReconnectPolicy::default()
+    };
+    assert_eq!(policy.backoff(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff(2), Duration::from_millis(200));
+    assert_eq!(policy.backoff(4), Duration::from_millis(800));
+    assert_eq!(policy.backoff(5), Duration::from_secs(1));
+    assert_eq!(policy.backoff(100), Duration::from_secs(1));
+
+    let jittered = ReconnectPolicy {
+        jitter: 0.5,
+        ..policy
+    };
+    for _ in 0..100 {
+        let delay = jittered.backoff(3);
+        assert!(delay > Duration::from_millis(200) && delay <= Duration::from_millis(400));
+    }
+}
+
+#[test]
+fn test_replay_follows_idempotency() {
+    let add = client_message::Message::AddRequest(AddRequest { a: 1, b: 2 });
+    assert!(Replay::Idempotent.allows(&echo("hi")));
+    assert!(Replay::Idempotent.allows(&add));
+    assert!(Replay::Always.allows(&add));
+    assert!(!Replay::Never.allows(&echo("hi")));
+
+    let auth = client_message::Message::AuthRequest(AuthRequest {
+        token: "t0k3n".to_string(),
+    });
+    assert!(!Replay::Idempotent.allows(&auth));
+    assert!(!Replay::Always.allows(&auth));
+}
+
+#[test]
+fn test_reconnects_after_server_restart() {
+    let server = create_server("localhost:0");
+    let addr = server.local_addr();
+    let (mut client, states) = recording_client(addr.port(), fast_policy(20, Replay::Idempotent));
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(client.echo("before").unwrap(), "before");
+
+    server.stop();
+    drop(server);
+    let _server = create_server(&addr.to_string());
+
+    assert_eq!(client.echo("after").unwrap(), "after");
+    assert!(client.is_connected());
+
+    let states = states.lock().unwrap();
+    assert_eq!(states.first(), Some(&ConnectionState::Connected));
+    assert!(states.contains(&ConnectionState::Disconnected));
+    assert!(states
+        .iter()
+        .any(|state| matches!(state, ConnectionState::Reconnecting { attempt: 1, .. })));
+    assert_eq!(states.last(), Some(&ConnectionState::Connected));
+}
+
+#[test]
+fn test_unanswered_requests_are_replayed() {
+    let server = create_server("localhost:0");
+    let addr = server.local_addr();
+    let (mut client, _) = recording_client(addr.port(), fast_policy(20, Replay::Idempotent));
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(client.echo("warm up").unwrap(), "warm up");
+
+    server.stop();
+    drop(server);
+    // Sent into a connection the server has already closed
+    let first = client.send_request(echo("first")).unwrap_or_default();
+    let _server = create_server(&addr.to_string());
+    let second = client.send_request(echo("second")).unwrap();
+
+    for (request_id, content) in [(first, "first"), (second, "second")] {
+        if request_id == 0 {
+            continue; // The send itself noticed the closed connection
+        }
+        let response = client.receive_response(request_id).unwrap();
+        assert_eq!(response.request_id, request_id);
+        match response.message {
+            Some(server_message::Message::EchoMessage(e)) => {
+                assert_eq!(e.content, content)
+            }
+            other => panic!("Expected an echo, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_requests_fail_when_replay_is_disabled() {
+    let server = create_server("localhost:0");
+    let addr = server.local_addr();
+    let (mut client, _) = recording_client(addr.port(), fast_policy(20, Replay::Never));
+    client.connect().expect("Failed to connect to the server");
+    let request_id = client.send_request(echo("lost")).unwrap();
+
+    // Give the server time to answer before it stops
+    std::thread::sleep(Duration::from_millis(100));
+    server.stop();
+    drop(server);
+    let _server = create_server(&addr.to_string());
+
+    // The answer arrived before the restart
+    assert_eq!(
+        client.receive_response(request_id).unwrap().request_id,
+        request_id
+    );
+    // Sent into the closed connection, so it fails instead of being replayed
+    assert!(matches!(client.echo("lost"), Err(Error::Disconnected)));
+    assert!(client.is_connected());
+    assert_eq!(client.echo("fresh").unwrap(), "fresh");
+}
+
+#[test]
+fn test_gives_up_after_max_attempts() {
+    let server = create_server("localhost:0");
+    let addr = server.local_addr();
+    let (mut client, states) = recording_client(addr.port(), fast_policy(3, Replay::Idempotent));
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(client.echo("before").unwrap(), "before");
+
+    server.stop();
+    drop(server);
+
+    assert!(client.echo("nobody home").is_err());
+    assert!(!client.is_connected());
+    let attempts = states
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|state| matches!(state, ConnectionState::Reconnecting { .. }))
+        .count();
+    assert_eq!(attempts, 3);
+    assert_eq!(
+        states.lock().unwrap().last(),
+        Some(&ConnectionState::GaveUp)
+    );
+
+    // The next request starts over once the server is back
+    let _server = create_server(&addr.to_string());
+    assert_eq!(client.echo("back").unwrap(), "back");
+}
+
+#[test]
+fn test_without_policy_errors_are_returned() {
+    let server = create_server("localhost:0");
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(client.echo("hi").unwrap(), "hi");
+
+    server.stop();
+    assert!(client.echo("gone").is_err());
+    assert!(client.echo("still gone").is_err());
+}
+
+/// Server accepting one token, counting how often it is presented
+fn create_auth_server(addr: &str, attempts: &Arc<AtomicUsize>) -> ServerHandle {
+    let attempts = Arc::clone(attempts);
+    ServerBuilder::new(addr)
+        .with_authenticator(move |token: &str| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            match token {
+                "t0k3n" => Ok("device-42".to_string()),
+                _ => Err(AuthError::Unknown),
+            }
+        })
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+#[test]
+fn test_auth_requests_are_not_replayed() {
+    let before = Arc::new(AtomicUsize::new(0));
+    let server = create_auth_server("localhost:0", &before);
+    let addr = server.local_addr();
+    let (client, _) = recording_client(addr.port(), fast_policy(20, Replay::Idempotent));
+    let mut client = client.with_auth_token("t0k3n");
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(before.load(Ordering::SeqCst), 1);
+
+    server.stop();
+    drop(server);
+    let after = Arc::new(AtomicUsize::new(0));
+    let _server = create_auth_server(&addr.to_string(), &after);
+
+    // Sent into the closed connection; not idempotent, so never resent
+    let auth = client.send_request(client_message::Message::AuthRequest(AuthRequest {
+        token: "t0k3n".to_string(),
+    }));
+    assert_eq!(client.echo("after").unwrap(), "after");
+    if let Ok(request_id) = auth {
+        assert!(matches!(
+            client.receive_response(request_id),
+            Err(Error::Disconnected)
+        ));
+    }
+    // Only the client's own authentication of the new connection
+    assert_eq!(after.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_failed_send_is_not_reported_after_reconnecting() {
+    // Stands in for a server that resets the connection and goes away
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (mut client, _) = recording_client(port, fast_policy(2, Replay::Idempotent));
+    client.connect().expect("Failed to connect to the server");
+    let (accepted, _) = listener.accept().unwrap();
+    SockRef::from(&accepted)
+        .set_linger(Some(Duration::ZERO))
+        .unwrap();
+    drop(accepted);
+    drop(listener);
+    thread::sleep(Duration::from_millis(50));
+
+    // Writing into the reset connection fails and reconnecting gives up
+    assert!(client.send(echo("lost")).is_err());
+
+    let _server = create_server(&format!("127.0.0.1:{}", port));
+    client.send(echo("after")).unwrap();
+    match client.receive().unwrap().message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "after"),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+}
@@ -0,0 +1,117 @@
+use embedded_recruitment_task::{
+    client::Client,
+    message::{client_message, server_message, EchoMessage},
+    server::{Backend, ConfigError, Server, ServerBuilder},
+};
+use std::{
+    io::Read,
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn test_builder_applies_configuration() {
+    let server = ServerBuilder::new("localhost:0")
+        .with_workers(3)
+        .with_max_connections(5)
+        .with_max_frame_size(1024)
+        .with_nodelay(true)
+        .with_keepalive(Duration::from_secs(30))
+        .with_backlog(16)
+        .with_log_payloads(true)
+        .build()
+        .expect("Failed to build server");
+
+    let config = server.config();
+    assert_eq!(config.workers, 3);
+    assert_eq!(config.max_connections, 5);
+    assert_eq!(config.max_frame_size, 1024);
+    assert!(config.nodelay);
+    assert_eq!(config.keepalive, Some(Duration::from_secs(30)));
+    assert_eq!(config.backlog, 16);
+
+    // The configured server still answers requests
+    let server = server.spawn().unwrap();
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    let message = client_message::Message::EchoMessage(EchoMessage {
+        content: "configured".to_string(),
+    });
+    assert!(client.send(message).is_ok());
+    match client.receive().unwrap().message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "configured"),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_builder_rejects_invalid_values() {
+    let error = Server::builder("localhost:0")
+        .with_workers(0)
+        .build()
+        .err()
+        .expect("Zero workers was accepted");
+    assert!(matches!(error, ConfigError::Invalid { key: "workers", .. }));
+    assert_eq!(error.to_string(), "invalid workers: must be at least 1");
+
+    let error = Server::builder("localhost:0")
+        .with_read_timeout(Duration::ZERO)
+        .build()
+        .err()
+        .expect("Zero read timeout was accepted");
+    assert!(matches!(
+        error,
+        ConfigError::Invalid {
+            key: "read_timeout",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_builder_rejects_conflicting_settings() {
+    let error = Server::builder("localhost:0")
+        .with_backend(Backend::EventLoop)
+        .with_read_timeout(Duration::from_secs(1))
+        .build()
+        .err()
+        .expect("Read timeout with the event loop was accepted");
+    assert!(matches!(
+        error,
+        ConfigError::Conflict {
+            key: "read_timeout",
+            other: "backend",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_builder_reports_bind_failures() {
+    let error = Server::builder("not an address")
+        .build()
+        .err()
+        .expect("Unresolvable address was accepted");
+    assert!(matches!(error, ConfigError::Io(_)));
+}
+
+#[test]
+fn test_read_timeout_closes_idle_connections() {
+    let server = Server::builder("localhost:0")
+        .with_read_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap()
+        .spawn()
+        .unwrap();
+
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // The server hangs up on its own once the client has been silent
+    let started = Instant::now();
+    let mut buffer = [0u8; 16];
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
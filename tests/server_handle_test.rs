@@ -0,0 +1,42 @@
+use embedded_recruitment_task::{
+    client::Client,
+    message::{client_message, EchoMessage},
+    server::{Server, ShutdownReport},
+};
+use std::net::TcpStream;
+
+#[test]
+fn test_spawned_server_serves_requests() {
+    let server = Server::new("localhost:0").unwrap().spawn().unwrap();
+    assert_eq!(server.local_addr().port(), server.get_port());
+
+    let mut client = Client::new("localhost", server.get_port().into(), 1000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    let message = client_message::Message::EchoMessage(EchoMessage {
+        content: "Hello".to_string(),
+    });
+    assert!(client.send(message).is_ok());
+    assert!(client.receive().is_ok());
+
+    // The connected client is drained by the shutdown
+    let report = server.stop();
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 1,
+            forced: 0
+        }
+    );
+    assert_eq!(server.join().unwrap(), report);
+}
+
+#[test]
+fn test_dropping_the_handle_stops_the_server() {
+    let server = Server::new("localhost:0").unwrap().spawn().unwrap();
+    let addr = server.local_addr();
+    assert!(TcpStream::connect(addr).is_ok());
+
+    // Drop blocks until the server thread is gone and the listener closed
+    drop(server);
+    assert!(TcpStream::connect(addr).is_err());
+}
@@ -0,0 +1,147 @@
+use embedded_recruitment_task::{
+    client::Client,
+    handler::{self, MessageKind},
+    message::{client_message, server_message, EchoMessage},
+    server::{Backend, Server, ServerBuilder, ShutdownReport},
+};
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// Echo handler that takes a while for requests asking to be slow
+fn sleepy_echo(request: client_message::Message) -> server_message::Message {
+    if let client_message::Message::EchoMessage(ref echo) = request {
+        if echo.content == "slow" {
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+    handler::echo(request)
+}
+
+fn setup_server(server: Server) -> (Arc<Server>, JoinHandle<()>) {
+    let server = Arc::new(server.with_handler(MessageKind::Echo, sleepy_echo));
+    let handle = {
+        let server = server.clone();
+        thread::spawn(move || server.run().expect("Server encountered an error"))
+    };
+    (server, handle)
+}
+
+fn echo(content: &str) -> client_message::Message {
+    client_message::Message::EchoMessage(EchoMessage {
+        content: content.to_string(),
+    })
+}
+
+fn connect(server: &Server) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 2000);
+    assert!(client.connect().is_ok(), "Failed to connect to the server");
+    client
+}
+
+// Stops the server while one client idles and another waits on a slow
+// request, and checks the slow request is still answered
+fn drains_in_flight_requests(backend: Backend) {
+    let (server, handle) = setup_server(
+        ServerBuilder::new("localhost:0")
+            .with_backend(backend)
+            .build()
+            .unwrap(),
+    );
+    let mut idle = connect(&server);
+    let mut busy = connect(&server);
+
+    // Make sure the idle client has been accepted before stopping
+    idle.send(echo("ping")).unwrap();
+    assert!(idle.receive().is_ok());
+    let request_id = busy.send_request(echo("slow")).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let report = server.stop();
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 2,
+            forced: 0
+        }
+    );
+
+    let response = busy
+        .receive_response(request_id)
+        .expect("Slow request was dropped");
+    match response.message {
+        Some(server_message::Message::EchoMessage(echo)) => assert_eq!(echo.content, "slow"),
+        other => panic!("Expected EchoMessage, got {:?}", other),
+    }
+    // Both connections are closed afterwards
+    assert!(idle.receive().is_err());
+    assert!(busy.receive().is_err());
+
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+// Stops the server with a request that outlives the shutdown timeout
+fn closes_connections_at_the_deadline(backend: Backend) {
+    let server = ServerBuilder::new("localhost:0")
+        .with_backend(backend)
+        .with_shutdown_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let (server, handle) = setup_server(server);
+    let mut busy = connect(&server);
+
+    busy.send_request(echo("slow")).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    let report = server.stop();
+    assert!(started.elapsed() < Duration::from_millis(400));
+    assert_eq!(
+        report,
+        ShutdownReport {
+            drained: 0,
+            forced: 1
+        }
+    );
+
+    // The connection is closed without an answer
+    assert!(busy.receive().is_err());
+
+    assert!(
+        handle.join().is_ok(),
+        "Server thread panicked or failed to join"
+    );
+}
+
+#[test]
+fn test_threaded_drains_in_flight_requests() {
+    drains_in_flight_requests(Backend::Threaded);
+}
+
+#[test]
+fn test_event_loop_drains_in_flight_requests() {
+    drains_in_flight_requests(Backend::EventLoop);
+}
+
+#[test]
+fn test_threaded_closes_connections_at_the_deadline() {
+    closes_connections_at_the_deadline(Backend::Threaded);
+}
+
+#[test]
+fn test_event_loop_closes_connections_at_the_deadline() {
+    closes_connections_at_the_deadline(Backend::EventLoop);
+}
+
+#[test]
+fn test_stop_without_run_reports_nothing() {
+    let server = Server::new("localhost:0").unwrap();
+    assert_eq!(server.stop(), ShutdownReport::default());
+    // A second stop is harmless
+    assert_eq!(server.stop(), ShutdownReport::default());
+}
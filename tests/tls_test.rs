@@ -0,0 +1,290 @@
+#![cfg(feature = "tls")]
+
+use embedded_recruitment_task::{
+    client::Client,
+    handler::{with_context, ConnectionContext, MessageKind},
+    message::{client_message, server_message, EchoMessage},
+    rustls::{
+        self,
+        pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+        ClientConfig, RootCertStore,
+    },
+    server::{Backend, ConfigError, ServerBuilder, ServerHandle},
+};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair, SanType};
+use std::{fs, path::PathBuf, process, sync::Arc};
+
+/// Self-signed certificate for `localhost`, written to PEM files
+struct TestCert {
+    cert: PathBuf,
+    key: PathBuf,
+    roots: RootCertStore,
+}
+
+impl TestCert {
+    fn generate(name: &str) -> Self {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .expect("Failed to generate a certificate");
+        let dir = std::env::temp_dir();
+        let cert = dir.join(format!("ert-{}-{}.crt", name, process::id()));
+        let key = dir.join(format!("ert-{}-{}.key", name, process::id()));
+        fs::write(&cert, generated.cert.pem()).unwrap();
+        fs::write(&key, generated.signing_key.serialize_pem()).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(generated.cert.der().clone()).unwrap();
+        TestCert { cert, key, roots }
+    }
+}
+
+impl Drop for TestCert {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.cert);
+        let _ = fs::remove_file(&self.key);
+    }
+}
+
+/// CA issuing client certificates, written to a PEM file for the server
+struct ClientCa {
+    path: PathBuf,
+    issuer: CertifiedIssuer<'static, KeyPair>,
+}
+
+impl ClientCa {
+    fn generate(name: &str) -> Self {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, format!("{} CA", name));
+        let issuer = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap())
+            .expect("Failed to generate a CA");
+        let path = std::env::temp_dir().join(format!("ert-{}-ca-{}.crt", name, process::id()));
+        fs::write(&path, issuer.pem()).unwrap();
+        ClientCa { path, issuer }
+    }
+
+    /// Client configuration trusting `roots` and presenting a certificate
+    /// for `name`, signed by this CA
+    fn client_config(&self, name: &str, roots: RootCertStore) -> Arc<ClientConfig> {
+        let mut params = CertificateParams::default();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "Fleet");
+        params.subject_alt_names = vec![
+            SanType::DnsName(format!("{}.example", name).try_into().unwrap()),
+            SanType::IpAddress("10.0.0.7".parse().unwrap()),
+        ];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.issuer).unwrap();
+        let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(key.serialize_der()));
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(vec![cert.der().clone()], key)
+            .expect("Failed to build the client configuration");
+        Arc::new(config)
+    }
+}
+
+impl Drop for ClientCa {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Server requiring client certificates from `ca`, whose echo handler
+/// answers with the verified identity
+fn create_mtls_server(cert: &TestCert, ca: &ClientCa) -> ServerHandle {
+    let whoami = with_context(|context: &ConnectionContext, _| {
+        let content = match context.identity() {
+            Some(identity) => format!("{} {:?}", identity.subject, identity.sans),
+            None => "anonymous".to_string(),
+        };
+        server_message::Message::EchoMessage(EchoMessage { content })
+    });
+    ServerBuilder::new("localhost:0")
+        .with_tls(&cert.cert, &cert.key)
+        .with_tls_client_ca(&ca.path)
+        .with_handler(MessageKind::Echo, whoami)
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+fn create_server(cert: &TestCert) -> ServerHandle {
+    ServerBuilder::new("localhost:0")
+        .with_tls(&cert.cert, &cert.key)
+        .build()
+        .expect("Failed to build the server")
+        .spawn()
+        .expect("Failed to start server")
+}
+
+fn connect(server: &ServerHandle, roots: RootCertStore) -> Client {
+    let mut client = Client::new("localhost", server.get_port().into(), 1000).with_tls(roots);
+    client.connect().expect("Failed to connect to the server");
+    client
+}
+
+#[test]
+fn test_requests_over_tls() {
+    let cert = TestCert::generate("requests");
+    let server = create_server(&cert);
+    let mut client = connect(&server, cert.roots.clone());
+
+    assert_eq!(client.echo("Hello over TLS").unwrap(), "Hello over TLS");
+    assert_eq!(client.add(40, 2).unwrap(), 42);
+    assert!(client.disconnect().is_ok());
+}
+
+#[test]
+fn test_pipelined_requests_over_tls() {
+    let cert = TestCert::generate("pipelined");
+    let server = create_server(&cert);
+    let mut client = connect(&server, cert.roots.clone());
+
+    // Responses are written by the workers while the reader keeps reading
+    let ids: Vec<u64> = (0..50)
+        .map(|i| {
+            let message = client_message::Message::EchoMessage(EchoMessage {
+                content: format!("message {}", i),
+            });
+            client.send_request(message).unwrap()
+        })
+        .collect();
+    for (i, id) in ids.into_iter().enumerate() {
+        match client.receive_response(id).unwrap().message {
+            Some(server_message::Message::EchoMessage(echo)) => {
+                assert_eq!(echo.content, format!("message {}", i))
+            }
+            other => panic!("Expected an echo, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_untrusted_certificate_is_rejected() {
+    let cert = TestCert::generate("untrusted");
+    let other = TestCert::generate("other");
+    let server = create_server(&cert);
+
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_tls(other.roots.clone());
+    assert!(client.connect().is_err());
+    assert!(!client.is_connected());
+
+    // The server keeps serving clients that do trust it
+    let mut client = connect(&server, cert.roots.clone());
+    assert_eq!(client.echo("still here").unwrap(), "still here");
+}
+
+#[test]
+fn test_plaintext_client_is_not_served() {
+    let cert = TestCert::generate("plaintext");
+    let server = create_server(&cert);
+
+    let mut client = Client::new("localhost", server.get_port().into(), 500);
+    client.connect().unwrap();
+    assert!(client.echo("in the clear").is_err());
+}
+
+#[test]
+fn test_tls_configuration_errors() {
+    let cert = TestCert::generate("config");
+
+    let error = ServerBuilder::new("localhost:0")
+        .with_tls(&cert.cert, &cert.key)
+        .with_backend(Backend::EventLoop)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error,
+        ConfigError::Conflict {
+            key: "tls_cert",
+            other: "backend",
+            ..
+        }
+    ));
+
+    let missing = std::env::temp_dir().join("ert-missing.crt");
+    let error = ServerBuilder::new("localhost:0")
+        .with_tls(&missing, &cert.key)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(error, ConfigError::Read { ref path, .. } if *path == missing));
+
+    // A key file in place of the certificate
+    let error = ServerBuilder::new("localhost:0")
+        .with_tls(&cert.key, &cert.key)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "tls_cert"));
+}
+
+#[test]
+fn test_client_certificate_identity_reaches_handlers() {
+    let cert = TestCert::generate("mtls");
+    let ca = ClientCa::generate("mtls");
+    let server = create_mtls_server(&cert, &ca);
+
+    let config = ca.client_config("device-42", cert.roots.clone());
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_tls_config(config);
+    client.connect().expect("Failed to connect to the server");
+    assert_eq!(
+        client.echo("who am I?").unwrap(),
+        "CN=device-42, O=Fleet [\"device-42.example\", \"10.0.0.7\"]"
+    );
+    // Other requests are served as usual
+    assert_eq!(client.add(40, 2).unwrap(), 42);
+}
+
+#[test]
+fn test_client_without_trusted_certificate_is_refused() {
+    let cert = TestCert::generate("mtls-refused");
+    let ca = ClientCa::generate("mtls-refused");
+    let other = ClientCa::generate("mtls-other");
+    let server = create_mtls_server(&cert, &ca);
+
+    // With TLS 1.3 the client finishes its side of the handshake before the
+    // server checks the certificate, so the refusal may surface on the
+    // first request
+    let refused = |mut client: Client| client.connect().is_err() || client.echo("hi").is_err();
+
+    let anonymous =
+        Client::new("localhost", server.get_port().into(), 1000).with_tls(cert.roots.clone());
+    assert!(refused(anonymous));
+
+    let config = other.client_config("intruder", cert.roots.clone());
+    let untrusted =
+        Client::new("localhost", server.get_port().into(), 1000).with_tls_config(config);
+    assert!(refused(untrusted));
+
+    // Clients with a certificate from the right CA are still served
+    let config = ca.client_config("device-1", cert.roots.clone());
+    let mut client =
+        Client::new("localhost", server.get_port().into(), 1000).with_tls_config(config);
+    client.connect().expect("Failed to connect to the server");
+    assert!(client.echo("hi").unwrap().starts_with("CN=device-1"));
+}
+
+#[test]
+fn test_client_ca_must_hold_a_certificate() {
+    let cert = TestCert::generate("mtls-config");
+    let error = ServerBuilder::new("localhost:0")
+        .with_tls(&cert.cert, &cert.key)
+        .with_tls_client_ca(&cert.key)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(error, ConfigError::Value { ref key, .. } if key == "tls_client_ca"));
+}